@@ -0,0 +1,63 @@
+use std::{fs::File, io, process};
+
+use zamlock::simulation::{simulate, SimulationSettings};
+
+const USAGE: &str =
+    "usage: simulate <map path> [battles] [--seed <seed>] [--max-turns <turns>] [--out <csv path>]";
+
+/// Plays AI-vs-AI battles on a map without a window and reports how each class fared as CSV,
+/// written to stdout unless `--out` is given. The map path is relative to the assets folder,
+/// e.g. `maps/skirmish.map.ron`.
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("{}", error);
+        eprintln!("{}", USAGE);
+        process::exit(1);
+    }
+}
+
+fn run() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+
+    let mut map = None;
+    let mut battles = None;
+    let mut seed = 0;
+    let mut max_turns = 50;
+    let mut out = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| anyhow::anyhow!("{} needs a value", arg))
+        };
+
+        match arg.as_str() {
+            "--seed" => seed = value()?.parse()?,
+            "--max-turns" => max_turns = value()?.parse()?,
+            "--out" => out = Some(value()?),
+            _ if map.is_none() => map = Some(arg),
+            _ if battles.is_none() => battles = Some(arg.parse()?),
+            _ => anyhow::bail!("unexpected argument {}", arg),
+        }
+    }
+
+    let settings = SimulationSettings {
+        map: map.ok_or_else(|| anyhow::anyhow!("no map given"))?,
+        battles: battles.unwrap_or(100),
+        seed,
+        max_turns,
+    };
+
+    let report = simulate(settings)?;
+    eprintln!("Simulated {} battles", report.battles());
+    if let Some(error) = report.error() {
+        eprintln!("{}", error);
+    }
+
+    match out {
+        Some(path) => report.write_csv(File::create(path)?)?,
+        None => report.write_csv(io::stdout().lock())?,
+    }
+
+    Ok(())
+}
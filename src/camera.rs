@@ -1,13 +1,87 @@
-use bevy::{prelude::*, render::camera::RenderTarget};
+use bevy::{
+    input::mouse::{MouseMotion, MouseWheel},
+    prelude::*,
+    render::camera::RenderTarget,
+};
+
+use crate::{
+    controls::InputAction,
+    tile_map::{ActiveTile, Map, Tile, TileCursor},
+    units::{MovementStarted, Unit, UnitDamagedEvent},
+};
+
+/// The zoom levels the scroll wheel steps through, as `OrthographicProjection` scales. Each is
+/// a whole number of screen pixels per sprite pixel (or sprite pixels per screen pixel), which
+/// keeps the pixel art crisp.
+const ZOOM_SCALES: [f32; 5] = [2.0, 1.0, 0.5, 1.0 / 3.0, 0.25];
+const DEFAULT_ZOOM: usize = 1;
+/// How fast edge scrolling pans the view, in screen pixels per second
+const PAN_SPEED: f32 = 300.0;
+/// How close to the edge of the window the cursor has to be to scroll the view, in pixels
+const EDGE_SCROLL_MARGIN: f32 = 8.0;
+/// How far a followed unit can stray from the centre of the view before the camera pans after
+/// it, as a fraction of the distance to the edge of the view
+const FOLLOW_DEAD_ZONE: f32 = 0.5;
+/// How quickly the camera eases towards a followed unit. Higher is snappier.
+const FOLLOW_SHARPNESS: f32 = 6.0;
 
 pub(crate) struct CameraPlugin;
 
 #[derive(Component)]
 pub struct MainCamera;
 
+/// What the main camera is looking at
+pub struct CameraView {
+    /// index into `ZOOM_SCALES`
+    zoom: usize,
+    /// bottom left and top right corners of the screen-space area covered by the map's
+    /// tiles, which the camera is kept over
+    bounds: Option<(Vec2, Vec2)>,
+    /// whether the camera pans to keep units in view as they move and get attacked
+    pub auto_follow: bool,
+    /// the unit being kept in view, until the player pans away or another unit takes over
+    following: Option<CameraFocus>,
+}
+
+/// Something the camera keeps in view
+#[derive(Clone, Copy)]
+enum CameraFocus {
+    Unit(Entity),
+    /// the tile under the `TileCursor`
+    Tile(Entity),
+}
+
+impl Default for CameraView {
+    fn default() -> Self {
+        CameraView {
+            zoom: DEFAULT_ZOOM,
+            bounds: None,
+            auto_follow: true,
+            following: None,
+        }
+    }
+}
+
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(Self::spawn_camera);
+        app.init_resource::<CameraView>()
+            .add_startup_system(Self::spawn_camera)
+            .add_system(Self::fit_to_map.label("fit_to_map"))
+            .add_system(Self::pan_camera.label("pan_camera").after("fit_to_map"))
+            .add_system(Self::zoom_camera.label("zoom_camera").after("pan_camera"))
+            .add_system(Self::toggle_follow.before("choose_followed"))
+            .add_system(Self::choose_followed.label("choose_followed"))
+            .add_system(
+                Self::follow_unit
+                    .label("follow_unit")
+                    .after("zoom_camera")
+                    .after("choose_followed"),
+            )
+            .add_system(
+                Self::clamp_camera
+                    .label("clamp_camera")
+                    .after("follow_unit"),
+            );
     }
 }
 
@@ -15,9 +89,258 @@ impl CameraPlugin {
     fn spawn_camera(mut commands: Commands) {
         let camera = OrthographicCameraBundle::new_2d();
         commands.spawn_bundle(camera).insert(MainCamera);
+        commands.spawn_bundle(UiCameraBundle::default());
+    }
+
+    /// Works out the bounds of the map whenever it's spawned or rotated, and centres the view
+    /// on newly spawned maps
+    fn fit_to_map(
+        mut view: ResMut<CameraView>,
+        map_query: Query<(&Map, ChangeTrackers<Map>), Changed<Map>>,
+        mut camera_query: Query<&mut Transform, With<MainCamera>>,
+    ) {
+        let (map, map_tracker) = match map_query.get_single() {
+            Ok(map) => map,
+            Err(_) => return,
+        };
+
+        let half_tile = Vec2::splat(map.tile_size / 2.0);
+        let mut corners = map.tiles().map(|(pos, _)| {
+            let screen_pos = map.world_pos_to_screen_pos_absolute(pos).truncate();
+            (screen_pos - half_tile, screen_pos + half_tile)
+        });
+
+        let first = match corners.next() {
+            Some(first) => first,
+            None => return,
+        };
+        let (min, max) = corners.fold(first, |(min, max), (tile_min, tile_max)| {
+            (min.min(tile_min), max.max(tile_max))
+        });
+        view.bounds = Some((min, max));
+
+        if !map_tracker.is_added() {
+            return;
+        }
+
+        let mut transform = camera_query.single_mut();
+        let centre = (min + max) / 2.0;
+        transform.translation.x = centre.x;
+        transform.translation.y = centre.y;
+    }
+
+    /// Pans by holding the pan actions, by dragging the mouse with `InputAction::DragCamera`
+    /// held, or by holding the cursor at the edge of the window
+    fn pan_camera(
+        time: Res<Time>,
+        wnds: Res<Windows>,
+        actions: Res<Input<InputAction>>,
+        mut view: ResMut<CameraView>,
+        mut motion_events: EventReader<MouseMotion>,
+        mut camera_query: Query<(&mut Transform, &OrthographicProjection), With<MainCamera>>,
+    ) {
+        let (mut transform, projection) = camera_query.single_mut();
+
+        // in screen pixels, with y up
+        let mut direction = Vec2::ZERO;
+
+        if actions.pressed(InputAction::PanUp) {
+            direction.y += 1.0;
+        }
+        if actions.pressed(InputAction::PanDown) {
+            direction.y -= 1.0;
+        }
+        if actions.pressed(InputAction::PanLeft) {
+            direction.x -= 1.0;
+        }
+        if actions.pressed(InputAction::PanRight) {
+            direction.x += 1.0;
+        }
+
+        let dragging = actions.pressed(InputAction::DragCamera);
+
+        if let Some(wnd) = wnds.get_primary() {
+            if let (false, Some(cursor)) = (dragging, wnd.cursor_position()) {
+                if cursor.x < EDGE_SCROLL_MARGIN {
+                    direction.x -= 1.0;
+                } else if cursor.x > wnd.width() - EDGE_SCROLL_MARGIN {
+                    direction.x += 1.0;
+                }
+                if cursor.y < EDGE_SCROLL_MARGIN {
+                    direction.y -= 1.0;
+                } else if cursor.y > wnd.height() - EDGE_SCROLL_MARGIN {
+                    direction.y += 1.0;
+                }
+            }
+        }
+
+        let mut offset = direction.clamp_length_max(1.0) * PAN_SPEED * time.delta_seconds();
+
+        for MouseMotion { delta } in motion_events.iter() {
+            if dragging {
+                // mouse motion has y pointing down the screen
+                offset += Vec2::new(-delta.x, delta.y);
+            }
+        }
+
+        if offset != Vec2::ZERO {
+            view.following = None;
+            transform.translation += (offset * projection.scale).extend(0.0);
+        }
+    }
+
+    /// Steps between `ZOOM_SCALES` with the scroll wheel, keeping whatever is under the
+    /// cursor in place
+    fn zoom_camera(
+        wnds: Res<Windows>,
+        mut view: ResMut<CameraView>,
+        mut wheel_events: EventReader<MouseWheel>,
+        mut camera_query: Query<(&mut Transform, &mut OrthographicProjection), With<MainCamera>>,
+    ) {
+        let scrolled = wheel_events.iter().map(|event| event.y).sum::<f32>();
+
+        let zoom = if scrolled > 0.0 {
+            (view.zoom + 1).min(ZOOM_SCALES.len() - 1)
+        } else if scrolled < 0.0 {
+            view.zoom.saturating_sub(1)
+        } else {
+            return;
+        };
+
+        if zoom == view.zoom {
+            return;
+        }
+
+        let (mut transform, mut projection) = camera_query.single_mut();
+        let old_scale = ZOOM_SCALES[view.zoom];
+        let new_scale = ZOOM_SCALES[zoom];
+
+        if let Some(cursor) = wnds.get_primary().and_then(|wnd| {
+            wnd.cursor_position()
+                .map(|cursor| cursor - Vec2::new(wnd.width(), wnd.height()) / 2.0)
+        }) {
+            transform.translation += (cursor * (old_scale - new_scale)).extend(0.0);
+        }
+
+        projection.scale = new_scale;
+        view.zoom = zoom;
+    }
+
+    /// Turns following units on and off
+    fn toggle_follow(actions: Res<Input<InputAction>>, mut view: ResMut<CameraView>) {
+        if actions.just_pressed(InputAction::ToggleCameraFollow) {
+            view.auto_follow = !view.auto_follow;
+            view.following = None;
+        }
+    }
+
+    /// Follows whichever unit most recently started moving or was attacked, or the
+    /// `TileCursor` whenever it moves
+    fn choose_followed(
+        mut view: ResMut<CameraView>,
+        cursor: Res<TileCursor>,
+        active_tile: Res<ActiveTile>,
+        mut started_events: EventReader<MovementStarted>,
+        mut damaged_events: EventReader<UnitDamagedEvent>,
+    ) {
+        let started = started_events.iter().map(|MovementStarted(entity)| *entity);
+        let damaged = damaged_events
+            .iter()
+            .map(|UnitDamagedEvent(target, _, _)| *target);
+
+        if let Some(entity) = started.chain(damaged).last() {
+            if view.auto_follow {
+                view.following = Some(CameraFocus::Unit(entity));
+            }
+        }
+
+        // the player's own cursor is always kept in view
+        if let (true, true, Some(tile)) = (cursor.active, active_tile.is_changed(), active_tile.0) {
+            view.following = Some(CameraFocus::Tile(tile));
+        }
+    }
+
+    /// Eases the view towards the followed unit or tile whenever it strays outside the dead
+    /// zone in the middle of the view
+    fn follow_unit(
+        time: Res<Time>,
+        wnds: Res<Windows>,
+        mut view: ResMut<CameraView>,
+        unit_query: Query<&Unit>,
+        tile_query: Query<&Tile>,
+        map_query: Query<&Map>,
+        mut camera_query: Query<(&mut Transform, &OrthographicProjection), With<MainCamera>>,
+    ) {
+        let pos = match view.following {
+            Some(CameraFocus::Unit(entity)) => unit_query.get(entity).map(|unit| unit.pos),
+            Some(CameraFocus::Tile(entity)) => tile_query.get(entity).map(|tile| tile.pos),
+            None => return,
+        };
+
+        let (pos, map) = match (pos, map_query.get_single()) {
+            (Ok(pos), Ok(map)) => (pos, map),
+            // defeated, or the battle is over
+            _ => {
+                view.following = None;
+                return;
+            }
+        };
+
+        let wnd = match wnds.get_primary() {
+            Some(wnd) => wnd,
+            None => return,
+        };
+
+        let (mut transform, projection) = camera_query.single_mut();
+
+        let target = map.world_pos_to_unit_screen_pos_absolute(pos).truncate();
+        let dead_zone =
+            Vec2::new(wnd.width(), wnd.height()) * projection.scale / 2.0 * FOLLOW_DEAD_ZONE;
+        let offset = target - transform.translation.truncate();
+        let outside_dead_zone = offset - offset.clamp(-dead_zone, dead_zone);
+
+        let ease = 1.0 - (-FOLLOW_SHARPNESS * time.delta_seconds()).exp();
+        transform.translation += (outside_dead_zone * ease).extend(0.0);
+    }
+
+    /// Keeps the view over the map (or centred on it, if the whole map fits in the window),
+    /// and lined up with the screen's pixels
+    fn clamp_camera(
+        wnds: Res<Windows>,
+        view: Res<CameraView>,
+        mut camera_query: Query<(&mut Transform, &OrthographicProjection), With<MainCamera>>,
+    ) {
+        let (mut transform, projection) = camera_query.single_mut();
+
+        if let (Some((min, max)), Some(wnd)) = (view.bounds, wnds.get_primary()) {
+            let half_view = Vec2::new(wnd.width(), wnd.height()) * projection.scale / 2.0;
+            let centre = (min + max) / 2.0;
+            let translation = transform.translation.truncate();
+
+            let clamp_axis = |value: f32, min: f32, max: f32, centre: f32, half_view: f32| {
+                if max - min <= half_view * 2.0 {
+                    centre
+                } else {
+                    value.clamp(min + half_view, max - half_view)
+                }
+            };
+
+            transform.translation.x =
+                clamp_axis(translation.x, min.x, max.x, centre.x, half_view.x);
+            transform.translation.y =
+                clamp_axis(translation.y, min.y, max.y, centre.y, half_view.y);
+        }
+
+        // one screen pixel is `scale` world units across
+        transform.translation.x =
+            (transform.translation.x / projection.scale).round() * projection.scale;
+        transform.translation.y =
+            (transform.translation.y / projection.scale).round() * projection.scale;
     }
 }
 
+/// Returns the world-space position of the mouse cursor, which takes the camera's pan and
+/// zoom into account
 pub(crate) fn mouse_pos_to_screen_pos(
     // need to get window dimensions
     wnds: Res<Windows>,
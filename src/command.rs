@@ -0,0 +1,120 @@
+use bevy::{ecs::event::Events, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    replay::Replay,
+    state::AppState,
+    tile_map::{DeselectUnitEvent, Pos, SelectUnitEvent},
+    turns::EndPhaseEvent,
+    units::{Action, DeployEvent, Faction, QueueActionsEvent, SelectedUnit, UndoMoveEvent, Unit},
+};
+
+/// Something the player or the AI tells the game to do.
+///
+/// Every change to a battle's state should start from one of these, so that a battle can be
+/// replayed from its commands. Units are referred to by where they stand rather than by
+/// `Entity`, since entities get different ids each time a battle is played.
+///
+/// The events that commands turn into (`DeployEvent`, `SelectUnitEvent`,
+/// `DeselectUnitEvent`, `QueueActionsEvent`, `UndoMoveEvent` and `EndPhaseEvent`) are only
+/// sent by `CommandPlugin`, with these exceptions, which follow from earlier commands and
+/// so play out the same way in a replay:
+/// - `ActionQueue`s send `SetPathEvent`s and `AttackEvent`s for the actions they were given
+/// - the selected unit is deselected with `DeselectUnitEvent` when a phase ends
+///
+/// `Waypoints` and the selected unit's `SelectMode` are changed directly by the player's
+/// input, since they're only a plan until they're given as part of an `Act` command.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum BattleCommand {
+    /// place the next unit from the roster on a deployment tile, or take back the unit on it
+    Deploy(Pos),
+    BeginBattle,
+    SelectUnit(Pos),
+    DeselectUnit,
+    /// add actions to the end of the `ActionQueue` of the unit at the given `Pos`
+    Act(Pos, Vec<Action>),
+    UndoMove,
+    EndPhase(Faction),
+}
+
+/// Sent by the player's input handling and the AI to give a `BattleCommand`
+pub struct CommandEvent(pub BattleCommand);
+
+pub(crate) struct CommandPlugin;
+
+impl Plugin for CommandPlugin {
+    fn build(&self, app: &mut App) {
+        // owned here rather than by `ReplayPlugin`, since every command passes through it
+        app.insert_resource(Replay::default())
+            .add_event::<CommandEvent>()
+            .add_system_set(
+                SystemSet::on_update(AppState::Deployment)
+                    .with_system(Self::apply_commands.label("apply_commands")),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Deployment).with_system(Self::clear_commands),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Battle)
+                    .with_system(Self::apply_commands.label("apply_commands")),
+            );
+    }
+}
+
+impl CommandPlugin {
+    /// Turns commands into the events that carry them out. While a replay is playing, only
+    /// its commands are applied.
+    #[allow(clippy::too_many_arguments)]
+    fn apply_commands(
+        mut events: EventReader<CommandEvent>,
+        mut replay: ResMut<Replay>,
+        mut state: ResMut<State<AppState>>,
+        selected_unit: Res<SelectedUnit>,
+        unit_query: Query<(Entity, &Unit)>,
+        mut deploy_events: EventWriter<DeployEvent>,
+        mut select_events: EventWriter<SelectUnitEvent>,
+        mut deselect_events: EventWriter<DeselectUnitEvent>,
+        mut queue_events: EventWriter<QueueActionsEvent>,
+        mut undo_events: EventWriter<UndoMoveEvent>,
+        mut end_events: EventWriter<EndPhaseEvent>,
+    ) {
+        let commands = replay.take_commands(events.iter().map(|CommandEvent(command)| command));
+
+        let unit_at = |pos: Pos| {
+            unit_query
+                .iter()
+                .find(|(_, unit)| unit.pos == pos)
+                .map(|(entity, _)| entity)
+        };
+
+        for command in commands {
+            match command {
+                BattleCommand::Deploy(pos) => deploy_events.send(DeployEvent(pos)),
+                BattleCommand::BeginBattle => {
+                    state.set(AppState::Battle).expect("Couldn't start battle");
+                }
+                BattleCommand::SelectUnit(pos) => match unit_at(pos) {
+                    Some(entity) => select_events.send(SelectUnitEvent(entity)),
+                    None => warn!("Ignoring selection of empty tile {:?}", pos),
+                },
+                BattleCommand::DeselectUnit => {
+                    if let SelectedUnit::Some { entity, mode: _ } = *selected_unit {
+                        deselect_events.send(DeselectUnitEvent(entity));
+                    }
+                }
+                BattleCommand::Act(pos, actions) => match unit_at(pos) {
+                    Some(entity) => queue_events.send(QueueActionsEvent(entity, actions)),
+                    None => warn!("Ignoring actions for empty tile {:?}", pos),
+                },
+                BattleCommand::UndoMove => undo_events.send(UndoMoveEvent),
+                BattleCommand::EndPhase(faction) => end_events.send(EndPhaseEvent(faction)),
+            }
+        }
+    }
+
+    /// Drops the commands that have already been applied during deployment, which would
+    /// otherwise be applied a second time by the battle's `apply_commands`
+    fn clear_commands(mut events: ResMut<Events<CommandEvent>>) {
+        events.clear();
+    }
+}
@@ -0,0 +1,357 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use bevy::{input::InputSystem, prelude::*};
+use serde::{Deserialize, Serialize};
+
+/// Where the player's control bindings are kept, relative to the working directory
+pub const CONTROLS_PATH: &str = "config/controls.ron";
+
+/// Something the player does by pressing a button, whichever buttons are bound to it.
+///
+/// Gameplay systems read these from `Input<InputAction>` rather than reading the keyboard,
+/// mouse or gamepads directly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum InputAction {
+    /// select, move, attack or deploy on the active tile
+    Confirm,
+    /// step back out of whatever the player is in the middle of choosing
+    Cancel,
+    /// start a battle from the title menu, begin it once deployment is done, or leave the
+    /// results screen
+    Start,
+    CursorUp,
+    CursorDown,
+    CursorLeft,
+    CursorRight,
+    /// held while confirming a move to add a waypoint instead
+    AddWaypoint,
+    EndPhase,
+    Undo,
+    RotateClockwise,
+    RotateAnticlockwise,
+    /// held while moving the mouse to pan the view
+    DragCamera,
+    /// held to pan the view
+    PanUp,
+    PanDown,
+    PanLeft,
+    PanRight,
+    ToggleCameraFollow,
+    /// held to speed everything up
+    FastForward,
+    ToggleThreats,
+    QuickSave,
+    LoadSave,
+    PlayReplay,
+    ReplayPause,
+    ReplayStep,
+    ReplayFaster,
+    ReplaySlower,
+}
+
+/// A button that can be bound to an `InputAction`. Gamepad buttons work on any connected
+/// gamepad.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButtonType),
+}
+
+impl Binding {
+    /// What the button is called in prompts
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(key) => match key {
+                KeyCode::Return => "Enter".to_string(),
+                KeyCode::Escape => "Esc".to_string(),
+                KeyCode::LShift | KeyCode::RShift => "Shift".to_string(),
+                KeyCode::Equals => "=".to_string(),
+                KeyCode::Minus => "-".to_string(),
+                _ => format!("{:?}", key),
+            },
+            Binding::Mouse(button) => match button {
+                MouseButton::Left => "left mouse button".to_string(),
+                MouseButton::Right => "right mouse button".to_string(),
+                MouseButton::Middle => "middle mouse button".to_string(),
+                MouseButton::Other(button) => format!("mouse button {}", button),
+            },
+            Binding::Gamepad(button_type) => format!("{:?}", button_type),
+        }
+    }
+}
+
+/// Which buttons are bound to each `InputAction`, as read from `CONTROLS_PATH`
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Controls(pub BTreeMap<InputAction, Vec<Binding>>);
+
+impl Controls {
+    /// The first button bound to `action`, for telling the player what to press
+    pub fn prompt(&self, action: InputAction) -> String {
+        self.0
+            .get(&action)
+            .and_then(|bindings| bindings.first())
+            .map_or_else(|| "(unbound)".to_string(), Binding::name)
+    }
+
+    /// Returns a button that's bound to two different actions, along with both of them
+    pub fn find_duplicate(&self) -> Option<(Binding, InputAction, InputAction)> {
+        let bindings = self
+            .0
+            .iter()
+            .flat_map(|(action, bindings)| bindings.iter().map(move |binding| (*binding, *action)))
+            .collect::<Vec<_>>();
+
+        bindings
+            .iter()
+            .enumerate()
+            .find_map(|(index, (binding, action))| {
+                bindings[index + 1..]
+                    .iter()
+                    .find(|(other_binding, other_action)| {
+                        other_binding == binding && other_action != action
+                    })
+                    .map(|(_, other_action)| (*binding, *action, *other_action))
+            })
+    }
+}
+
+impl Default for Controls {
+    fn default() -> Self {
+        use Binding::*;
+        use GamepadButtonType::*;
+
+        Controls(BTreeMap::from([
+            (
+                InputAction::Confirm,
+                vec![
+                    Mouse(MouseButton::Left),
+                    Key(KeyCode::Space),
+                    Gamepad(South),
+                ],
+            ),
+            (
+                InputAction::Cancel,
+                vec![
+                    Mouse(MouseButton::Right),
+                    Key(KeyCode::Escape),
+                    Gamepad(East),
+                ],
+            ),
+            (
+                InputAction::Start,
+                vec![Key(KeyCode::Return), Gamepad(Start)],
+            ),
+            (
+                InputAction::CursorUp,
+                vec![Key(KeyCode::Up), Key(KeyCode::W), Gamepad(DPadUp)],
+            ),
+            (
+                InputAction::CursorDown,
+                vec![Key(KeyCode::Down), Key(KeyCode::S), Gamepad(DPadDown)],
+            ),
+            (
+                InputAction::CursorLeft,
+                vec![Key(KeyCode::Left), Key(KeyCode::A), Gamepad(DPadLeft)],
+            ),
+            (
+                InputAction::CursorRight,
+                vec![Key(KeyCode::Right), Key(KeyCode::D), Gamepad(DPadRight)],
+            ),
+            (
+                InputAction::AddWaypoint,
+                vec![
+                    Key(KeyCode::LShift),
+                    Key(KeyCode::RShift),
+                    Gamepad(LeftTrigger2),
+                ],
+            ),
+            (InputAction::EndPhase, vec![Key(KeyCode::E), Gamepad(North)]),
+            (InputAction::Undo, vec![Key(KeyCode::Z), Gamepad(West)]),
+            (
+                InputAction::RotateClockwise,
+                vec![Key(KeyCode::Q), Gamepad(RightTrigger)],
+            ),
+            (
+                InputAction::RotateAnticlockwise,
+                vec![Key(KeyCode::R), Gamepad(LeftTrigger)],
+            ),
+            (InputAction::DragCamera, vec![Mouse(MouseButton::Middle)]),
+            (InputAction::PanUp, vec![Key(KeyCode::Numpad8)]),
+            (InputAction::PanDown, vec![Key(KeyCode::Numpad2)]),
+            (InputAction::PanLeft, vec![Key(KeyCode::Numpad4)]),
+            (InputAction::PanRight, vec![Key(KeyCode::Numpad6)]),
+            (InputAction::ToggleCameraFollow, vec![Key(KeyCode::F)]),
+            (
+                InputAction::FastForward,
+                vec![Key(KeyCode::Tab), Gamepad(RightTrigger2)],
+            ),
+            (
+                InputAction::ToggleThreats,
+                vec![Key(KeyCode::T), Gamepad(Select)],
+            ),
+            (InputAction::QuickSave, vec![Key(KeyCode::F5)]),
+            (InputAction::LoadSave, vec![Key(KeyCode::L)]),
+            (InputAction::PlayReplay, vec![Key(KeyCode::V)]),
+            (InputAction::ReplayPause, vec![Key(KeyCode::P)]),
+            (InputAction::ReplayStep, vec![Key(KeyCode::N)]),
+            (InputAction::ReplayFaster, vec![Key(KeyCode::Equals)]),
+            (InputAction::ReplaySlower, vec![Key(KeyCode::Minus)]),
+        ]))
+    }
+}
+
+/// Turns key, mouse and gamepad presses into `InputAction`s
+pub(crate) struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Controls>()
+            .init_resource::<Input<InputAction>>()
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                Self::update_actions
+                    .label("update_actions")
+                    .after(InputSystem),
+            );
+    }
+}
+
+impl ControlsPlugin {
+    /// Presses each action while any of its bindings are held
+    fn update_actions(
+        controls: Res<Controls>,
+        keyboard: Res<Input<KeyCode>>,
+        mouse: Res<Input<MouseButton>>,
+        gamepads: Res<Gamepads>,
+        gamepad_buttons: Res<Input<GamepadButton>>,
+        mut actions: ResMut<Input<InputAction>>,
+    ) {
+        actions.clear();
+
+        for (action, bindings) in controls.0.iter() {
+            let held = bindings.iter().any(|binding| match binding {
+                Binding::Key(key) => keyboard.pressed(*key),
+                Binding::Mouse(button) => mouse.pressed(*button),
+                Binding::Gamepad(button_type) => gamepads
+                    .iter()
+                    .any(|gamepad| gamepad_buttons.pressed(GamepadButton(*gamepad, *button_type))),
+            });
+
+            if held && !actions.pressed(*action) {
+                actions.press(*action);
+            } else if !held && actions.pressed(*action) {
+                actions.release(*action);
+            }
+        }
+    }
+}
+
+/// Loads the player's `Controls` from `CONTROLS_PATH`, and saves them there whenever they
+/// change
+pub(crate) struct ControlsConfigPlugin;
+
+impl Plugin for ControlsConfigPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(Self::load_controls)
+            .add_system(Self::save_controls);
+    }
+}
+
+impl ControlsConfigPlugin {
+    /// Actions missing from the file keep their default bindings. If there's no file yet,
+    /// one is written with the default bindings for the player to edit. A file that binds
+    /// one button to two actions is ignored.
+    fn load_controls(mut controls: ResMut<Controls>) {
+        if !Path::new(CONTROLS_PATH).exists() {
+            if let Err(error) = write_controls(&controls) {
+                error!("Couldn't save controls: {}", error);
+            }
+            return;
+        }
+
+        // leave the file alone when it can't be used, so that the player can fix it
+        let mut loaded = controls.clone();
+        match read_controls() {
+            Ok(Controls(bindings)) => loaded.0.extend(bindings),
+            Err(error) => {
+                error!("Couldn't load controls, using defaults: {}", error);
+                return;
+            }
+        }
+
+        match loaded.find_duplicate() {
+            Some((binding, action, other_action)) => error!(
+                "Couldn't load controls, using defaults: {:?} is bound to both {:?} and {:?}",
+                binding, action, other_action
+            ),
+            None => *controls = loaded,
+        }
+    }
+
+    /// Saves any changes made to the `Controls` after startup
+    fn save_controls(controls: Res<Controls>) {
+        if !controls.is_changed() || controls.is_added() {
+            return;
+        }
+
+        match write_controls(&controls) {
+            Ok(()) => info!("Saved controls to {}", CONTROLS_PATH),
+            Err(error) => error!("Couldn't save controls: {}", error),
+        }
+    }
+}
+
+fn write_controls(controls: &Controls) -> anyhow::Result<()> {
+    if let Some(directory) = Path::new(CONTROLS_PATH).parent() {
+        fs::create_dir_all(directory)?;
+    }
+
+    let contents = ron::ser::to_string_pretty(controls, ron::ser::PrettyConfig::default())?;
+    fs::write(CONTROLS_PATH, contents)?;
+
+    Ok(())
+}
+
+fn read_controls() -> anyhow::Result<Controls> {
+    let bytes = fs::read(CONTROLS_PATH)?;
+
+    Ok(ron::de::from_bytes(&bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_controls_bind_each_button_once() {
+        assert_eq!(Controls::default().find_duplicate(), None);
+    }
+
+    #[test]
+    fn button_bound_twice_is_found() {
+        let mut controls = Controls::default();
+        controls
+            .0
+            .insert(InputAction::PlayReplay, vec![Binding::Key(KeyCode::Q)]);
+
+        assert_eq!(
+            controls.find_duplicate(),
+            Some((
+                Binding::Key(KeyCode::Q),
+                InputAction::RotateClockwise,
+                InputAction::PlayReplay
+            ))
+        );
+    }
+
+    #[test]
+    fn prompts_name_the_first_binding() {
+        let mut controls = Controls::default();
+        assert_eq!(controls.prompt(InputAction::Start), "Enter");
+        assert_eq!(controls.prompt(InputAction::PlayReplay), "V");
+
+        controls.0.remove(&InputAction::PlayReplay);
+        assert_eq!(controls.prompt(InputAction::PlayReplay), "(unbound)");
+    }
+}
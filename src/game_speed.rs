@@ -0,0 +1,75 @@
+use bevy::prelude::*;
+
+use crate::{controls::InputAction, state::AppState, turns::TurnState, units::AiControlled};
+
+/// Multiplier for how quickly gameplay plays out: movement, animations, pauses etc.
+///
+/// Systems should scale `Time::delta_seconds()` by `GameSpeed::multiplier()` rather than
+/// reading it directly.
+pub struct GameSpeed {
+    /// normal speed, 1.0 being real time
+    pub speed: f32,
+    /// speed used instead of `speed` while `fast_forwarding`, e.g. during enemy turns, unless
+    /// `speed` is already faster
+    pub fast_forward_speed: f32,
+    pub fast_forwarding: bool,
+    /// whether to fast forward through the phases of `AiControlled` factions without
+    /// having to hold `InputAction::FastForward`
+    pub fast_forward_ai_phases: bool,
+}
+
+impl Default for GameSpeed {
+    fn default() -> Self {
+        GameSpeed {
+            speed: 1.0,
+            fast_forward_speed: 3.0,
+            fast_forwarding: false,
+            fast_forward_ai_phases: true,
+        }
+    }
+}
+
+impl GameSpeed {
+    pub fn multiplier(&self) -> f32 {
+        if self.fast_forwarding {
+            self.fast_forward_speed.max(self.speed)
+        } else {
+            self.speed
+        }
+    }
+
+    /// Returns how much game time has passed this frame, in seconds
+    pub fn delta_seconds(&self, time: &Time) -> f32 {
+        time.delta_seconds() * self.multiplier()
+    }
+}
+
+pub(crate) struct GameSpeedPlugin;
+
+impl Plugin for GameSpeedPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameSpeed>()
+            .add_system(Self::fast_forward);
+    }
+}
+
+impl GameSpeedPlugin {
+    /// Fast forward while `InputAction::FastForward` is held, and through the AI's phases if
+    /// `GameSpeed::fast_forward_ai_phases` is set
+    fn fast_forward(
+        actions: Res<Input<InputAction>>,
+        state: Res<State<AppState>>,
+        turn_state: Res<TurnState>,
+        ai_controlled: Res<AiControlled>,
+        mut game_speed: ResMut<GameSpeed>,
+    ) {
+        let ai_phase =
+            *state.current() == AppState::Battle && ai_controlled.0.contains(&turn_state.phase);
+        let fast_forwarding = actions.pressed(InputAction::FastForward)
+            || (game_speed.fast_forward_ai_phases && ai_phase);
+
+        if game_speed.fast_forwarding != fast_forwarding {
+            game_speed.fast_forwarding = fast_forwarding;
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use bevy::{app::PluginGroupBuilder, prelude::*};
+
+mod camera;
+mod command;
+mod controls;
+mod game_speed;
+mod objectives;
+mod replay;
+mod rng;
+mod save;
+pub mod simulation;
+mod state;
+mod tile_map;
+mod turns;
+mod ui;
+mod units;
+mod versioned_file;
+
+use camera::CameraPlugin;
+use command::CommandPlugin;
+use controls::{ControlsConfigPlugin, ControlsPlugin};
+use game_speed::GameSpeedPlugin;
+use objectives::{ObjectiveGraphicsPlugin, ObjectivePlugin};
+use replay::ReplayPlugin;
+use rng::RngPlugin;
+use save::SavePlugin;
+use state::AppStatePlugin;
+use tile_map::{TileMapGraphicsPluginGroup, TileMapPluginGroup};
+use turns::TurnPlugin;
+use ui::UiPluginGroup;
+use units::{UnitGraphicsPluginGroup, UnitPluginGroup};
+
+const TILE_SIZE: f32 = 32.0;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub enum PreStartupSystemLabels {
+    LoadGraphics,
+    SpawnEntities,
+}
+
+/// Everything needed to play out a battle: maps, units, turns, combat and the AI.
+///
+/// Doesn't draw anything, so runs under `MinimalPlugins` as long as `AssetPlugin` (to load
+/// maps and unit classes) and `InputPlugin` are added too.
+pub struct GameplayPlugins;
+
+impl PluginGroup for GameplayPlugins {
+    fn build(&mut self, group: &mut PluginGroupBuilder) {
+        group
+            .add(AppStatePlugin)
+            .add(RngPlugin)
+            .add(CommandPlugin)
+            .add(ControlsPlugin)
+            .add(TurnPlugin)
+            .add(ObjectivePlugin)
+            .add(GameSpeedPlugin);
+        TileMapPluginGroup.build(group);
+        UnitPluginGroup.build(group);
+    }
+}
+
+/// The whole game as it's played in a window: `GameplayPlugins` plus graphics, menus, saving
+/// and replays
+pub struct GamePlugins;
+
+impl PluginGroup for GamePlugins {
+    fn build(&mut self, group: &mut PluginGroupBuilder) {
+        GameplayPlugins.build(group);
+        TileMapGraphicsPluginGroup.build(group);
+        UnitGraphicsPluginGroup.build(group);
+        UiPluginGroup.build(group);
+        group
+            .add(ObjectiveGraphicsPlugin)
+            .add(CameraPlugin)
+            .add(ControlsConfigPlugin)
+            .add(SavePlugin)
+            .add(ReplayPlugin);
+    }
+}
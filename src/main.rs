@@ -1,20 +1,5 @@
 use bevy::prelude::*;
-
-mod camera;
-mod tile_map;
-mod units;
-
-use camera::CameraPlugin;
-use tile_map::TileMapPluginGroup;
-use units::UnitPluginGroup;
-
-const TILE_SIZE: f32 = 32.0;
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
-pub enum PreStartupSystemLabels {
-    LoadGraphics,
-    SpawnEntities,
-}
+use zamlock::GamePlugins;
 
 fn main() {
     App::new()
@@ -25,8 +10,6 @@ fn main() {
             ..default()
         })
         .add_plugins(DefaultPlugins)
-        .add_plugins(TileMapPluginGroup)
-        .add_plugins(UnitPluginGroup)
-        .add_plugin(CameraPlugin)
+        .add_plugins(GamePlugins)
         .run()
 }
@@ -0,0 +1,256 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    state::{AppState, BattleOutcome},
+    tile_map::{spawn_overlay, CurrentMap, Map, MapDefinition, OverlayLayer, Pos},
+    turns::{PhaseStartedEvent, TurnState},
+    units::{Faction, StepCompleted, Unit, UnitDefeatedEvent, UnitName},
+};
+
+/// Something that wins or loses a battle, as declared in a map's `.map.ron` file.
+///
+/// The battle is won as soon as any objective is complete, and lost as soon as any is
+/// failed. It's also lost if the player has no units left.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Objective {
+    /// win by defeating every enemy unit
+    RoutEnemies,
+    /// win by defeating the unit with this `UnitName`
+    DefeatBoss(String),
+    /// win by lasting this many turns
+    SurviveTurns(u32),
+    /// win by moving any of the player's units onto one of these tiles
+    Escape(Vec<Pos>),
+    /// lose if the unit with this `UnitName` is defeated
+    Protect(String),
+    /// lose if an enemy unit reaches one of these tiles
+    Defend(Vec<Pos>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ObjectiveStatus {
+    InProgress,
+    Complete,
+    Failed,
+}
+
+impl Objective {
+    /// Describes what wins the battle, or for defeat conditions what loses it
+    pub fn describe(&self, turn_state: &TurnState) -> String {
+        match self {
+            Objective::RoutEnemies => "Defeat all enemies".to_string(),
+            Objective::DefeatBoss(name) => format!("Defeat {}", name),
+            Objective::SurviveTurns(turns) => format!(
+                "Survive {} turns ({} left)",
+                turns,
+                (turns + 1).saturating_sub(turn_state.turn)
+            ),
+            Objective::Escape(_) => "Reach the escape tiles".to_string(),
+            Objective::Protect(name) => format!("{} is defeated", name),
+            Objective::Defend(_) => "An enemy reaches the defended tiles".to_string(),
+        }
+    }
+
+    /// Whether this objective can only be failed (losing the battle), rather than completed
+    pub fn is_defeat_condition(&self) -> bool {
+        matches!(self, Objective::Protect(_) | Objective::Defend(_))
+    }
+
+    fn status(
+        &self,
+        units: &[(&Unit, Option<&UnitName>)],
+        turn_state: &TurnState,
+    ) -> ObjectiveStatus {
+        let is_named = |name: &String| {
+            units
+                .iter()
+                .any(|(_, unit_name)| matches!(unit_name, Some(unit_name) if unit_name.0 == *name))
+        };
+        let any_on = |faction: Faction, tiles: &[Pos]| {
+            units
+                .iter()
+                .any(|(unit, _)| unit.faction == faction && tiles.contains(&unit.pos))
+        };
+
+        let (complete, failed) = match self {
+            Objective::RoutEnemies => (
+                !units.iter().any(|(unit, _)| unit.faction == Faction::Enemy),
+                false,
+            ),
+            Objective::DefeatBoss(name) => (!is_named(name), false),
+            Objective::SurviveTurns(turns) => (turn_state.turn > *turns, false),
+            Objective::Escape(tiles) => (any_on(Faction::Player, tiles), false),
+            Objective::Protect(name) => (false, !is_named(name)),
+            Objective::Defend(tiles) => (false, any_on(Faction::Enemy, tiles)),
+        };
+
+        if failed {
+            ObjectiveStatus::Failed
+        } else if complete {
+            ObjectiveStatus::Complete
+        } else {
+            ObjectiveStatus::InProgress
+        }
+    }
+
+    /// Tiles to highlight on the map for this objective
+    pub fn tiles(&self) -> &[Pos] {
+        match self {
+            Objective::Escape(tiles) | Objective::Defend(tiles) => tiles,
+            _ => &[],
+        }
+    }
+}
+
+/// The current battle's objectives
+#[derive(Default)]
+pub struct Objectives(pub Vec<Objective>);
+
+#[derive(Default)]
+struct ObjectiveGraphics {
+    escape: Handle<Image>,
+    defend: Handle<Image>,
+}
+
+#[derive(Component)]
+struct ObjectiveOverlay;
+
+pub(crate) struct ObjectivePlugin;
+
+impl Plugin for ObjectivePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Objectives::default())
+            .add_system_set(
+                SystemSet::on_enter(AppState::Deployment).with_system(Self::load_objectives),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Battle)
+                    .with_system(Self::evaluate_objectives.label("evaluate_objectives")),
+            );
+    }
+}
+
+impl ObjectivePlugin {
+    fn load_objectives(
+        mut objectives: ResMut<Objectives>,
+        current_map: Res<CurrentMap>,
+        definitions: Res<Assets<MapDefinition>>,
+    ) {
+        let definition = definitions
+            .get(&current_map.0)
+            .expect("Map definition not loaded");
+
+        objectives.0 = definition.objectives.clone();
+    }
+
+    /// Checks the objectives whenever a phase starts, a unit steps onto a tile or a unit is
+    /// defeated, and ends the battle if any have been completed or failed
+    #[allow(clippy::too_many_arguments)]
+    fn evaluate_objectives(
+        mut state: ResMut<State<AppState>>,
+        mut outcome: ResMut<BattleOutcome>,
+        objectives: Res<Objectives>,
+        turn_state: Res<TurnState>,
+        unit_query: Query<(&Unit, Option<&UnitName>)>,
+        mut phase_events: EventReader<PhaseStartedEvent>,
+        mut step_events: EventReader<StepCompleted>,
+        mut defeated_events: EventReader<UnitDefeatedEvent>,
+    ) {
+        // a resumed battle's units are spawned on its first frame, so wait for them
+        if unit_query.is_empty() {
+            return;
+        }
+
+        let events = phase_events.iter().count()
+            + step_events.iter().count()
+            + defeated_events.iter().count();
+
+        if events == 0 {
+            return;
+        }
+
+        // defeated units only lose their `Unit` at the end of the frame
+        let units = unit_query
+            .iter()
+            .filter(|(unit, _)| unit.hp > 0)
+            .collect::<Vec<_>>();
+        let statuses = objectives
+            .0
+            .iter()
+            .map(|objective| objective.status(&units, &turn_state))
+            .collect::<Vec<_>>();
+
+        let player_routed = !units
+            .iter()
+            .any(|(unit, _)| unit.faction == Faction::Player);
+
+        *outcome = if player_routed || statuses.contains(&ObjectiveStatus::Failed) {
+            BattleOutcome::Defeat
+        } else if statuses.contains(&ObjectiveStatus::Complete) {
+            BattleOutcome::Victory
+        } else {
+            return;
+        };
+
+        state
+            .set(AppState::Results)
+            .expect("Couldn't leave battle state");
+    }
+}
+
+/// Marks the tiles that objectives refer to
+pub(crate) struct ObjectiveGraphicsPlugin;
+
+impl Plugin for ObjectiveGraphicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ObjectiveGraphics::default())
+            .add_startup_system(Self::load_overlay_graphics)
+            .add_system(Self::highlight_objective_tiles);
+    }
+}
+
+impl ObjectiveGraphicsPlugin {
+    fn load_overlay_graphics(assets: Res<AssetServer>, mut graphics: ResMut<ObjectiveGraphics>) {
+        graphics.escape = assets.load("tiles/tile_escape.png");
+        graphics.defend = assets.load("tiles/tile_defend.png");
+    }
+
+    /// Tints escape and defend tiles as soon as the map is spawned
+    fn highlight_objective_tiles(
+        mut commands: Commands,
+        objectives: Res<Objectives>,
+        map_query: Query<&Map, Added<Map>>,
+        graphics: Res<ObjectiveGraphics>,
+    ) {
+        let map = match map_query.get_single() {
+            Ok(map) => map,
+            Err(_) => return,
+        };
+
+        for objective in objectives.0.iter() {
+            let texture = match objective {
+                Objective::Escape(_) => graphics.escape.clone(),
+                Objective::Defend(_) => graphics.defend.clone(),
+                _ => continue,
+            };
+
+            for pos in objective.tiles() {
+                let tile_entity = match map.tile(*pos) {
+                    Some(tile_entity) => tile_entity,
+                    None => continue,
+                };
+
+                spawn_overlay(
+                    &mut commands,
+                    map,
+                    tile_entity,
+                    OverlayLayer::Objective,
+                    texture.clone(),
+                    default(),
+                )
+                .insert(ObjectiveOverlay);
+            }
+        }
+    }
+}
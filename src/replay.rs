@@ -0,0 +1,280 @@
+use std::path::Path;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    command::BattleCommand,
+    controls::InputAction,
+    game_speed::GameSpeed,
+    rng::GameRng,
+    state::AppState,
+    tile_map::{CurrentMap, Map},
+    units::{ActionQueue, UnitClasses},
+    versioned_file::{read_versioned, write_versioned, VersionedFile},
+};
+
+/// Where the most recently finished battle is recorded, relative to the working directory
+pub const REPLAY_PATH: &str = "replays/last_battle.ron";
+
+/// How long playback waits between commands once the previous one has played out, in (game
+/// speed adjusted) seconds
+const COMMAND_DELAY: f32 = 0.3;
+const MIN_PLAYBACK_SPEED: f32 = 0.25;
+const MAX_PLAYBACK_SPEED: f32 = 8.0;
+
+/// Everything needed to play a battle again: where it was fought, the seed it was played
+/// with, and every command given from deployment onwards
+#[derive(Deserialize, Serialize)]
+pub struct ReplayFile {
+    version: u32,
+    seed: u64,
+    /// asset path of the battle's `MapDefinition`
+    map: String,
+    commands: Vec<BattleCommand>,
+}
+
+impl ReplayFile {
+    /// Starts recording a battle on the map at asset path `map`, played with `seed`
+    pub(crate) fn new(seed: u64, map: String) -> ReplayFile {
+        ReplayFile {
+            version: ReplayFile::VERSION,
+            seed,
+            map,
+            commands: vec![],
+        }
+    }
+}
+
+impl VersionedFile for ReplayFile {
+    // also bump whenever `BattleCommand` changes shape
+    const VERSION: u32 = 1;
+    const NAME: &'static str = "replay";
+}
+
+pub struct Playback {
+    replay: ReplayFile,
+    /// index of the next command to play
+    next: usize,
+    /// the command that's been let through to be applied this frame, if any
+    due: Option<BattleCommand>,
+    delay: Timer,
+    paused: bool,
+}
+
+impl Playback {
+    pub(crate) fn new(replay: ReplayFile) -> Playback {
+        Playback {
+            replay,
+            next: 0,
+            due: None,
+            delay: Timer::from_seconds(COMMAND_DELAY, false),
+            paused: false,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn progress(&self) -> (usize, usize) {
+        (self.next, self.replay.commands.len())
+    }
+}
+
+/// Whether the current battle is being recorded, played back from a replay, or neither (e.g.
+/// when it was resumed from a save part way through)
+#[derive(Default)]
+pub enum Replay {
+    #[default]
+    Off,
+    Recording(ReplayFile),
+    Playing(Playback),
+}
+
+impl Replay {
+    pub fn is_playing(&self) -> bool {
+        matches!(self, Replay::Playing(_))
+    }
+
+    /// The seed that the battle being played back was recorded with
+    pub fn seed(&self) -> Option<u64> {
+        match self {
+            Replay::Playing(playback) => Some(playback.replay.seed),
+            _ => None,
+        }
+    }
+
+    /// Returns the commands to apply this frame given the ones that have just been issued,
+    /// recording them if need be.
+    ///
+    /// While playing, the replay's commands replace the issued ones, so that neither the
+    /// player nor the AI can interfere with it.
+    pub(crate) fn take_commands<'a>(
+        &mut self,
+        issued: impl Iterator<Item = &'a BattleCommand>,
+    ) -> Vec<BattleCommand> {
+        match self {
+            Replay::Off => issued.cloned().collect(),
+            Replay::Recording(replay) => {
+                let commands = issued.cloned().collect::<Vec<BattleCommand>>();
+                replay.commands.extend(commands.iter().cloned());
+                commands
+            }
+            Replay::Playing(playback) => playback.due.take().into_iter().collect(),
+        }
+    }
+}
+
+pub(crate) struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(AppState::TitleMenu).with_system(Self::start_playback),
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Deployment)
+                .with_system(Self::start_recording.after("seed_rng")),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::Deployment)
+                .with_system(Self::play_commands.before("apply_commands")),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::Battle)
+                .with_system(Self::play_commands.before("apply_commands")),
+        )
+        .add_system_set(SystemSet::on_enter(AppState::Results).with_system(Self::finish));
+    }
+}
+
+impl ReplayPlugin {
+    /// Plays back the last recorded battle on `InputAction::PlayReplay` on the title
+    /// menu, once its map has loaded
+    fn start_playback(
+        mut actions: ResMut<Input<InputAction>>,
+        mut state: ResMut<State<AppState>>,
+        mut replay: ResMut<Replay>,
+        mut current_map: ResMut<CurrentMap>,
+        asset_server: Res<AssetServer>,
+        unit_classes: Res<UnitClasses>,
+    ) {
+        if actions.clear_just_pressed(InputAction::PlayReplay) {
+            match read_versioned::<ReplayFile>(Path::new(REPLAY_PATH)) {
+                Ok(file) => {
+                    current_map.0 = asset_server.load(file.map.as_str());
+                    *replay = Replay::Playing(Playback::new(file));
+                }
+                Err(error) => error!("Couldn't load replay: {}", error),
+            }
+        }
+
+        if replay.is_playing()
+            && unit_classes.is_loaded(&asset_server)
+            && current_map.is_loaded(&asset_server)
+        {
+            state
+                .set(AppState::Deployment)
+                .expect("Couldn't start replay");
+        }
+    }
+
+    fn start_recording(
+        mut replay: ResMut<Replay>,
+        rng: Res<GameRng>,
+        current_map: Res<CurrentMap>,
+        asset_server: Res<AssetServer>,
+    ) {
+        if replay.is_playing() {
+            return;
+        }
+
+        let map = match asset_server.get_handle_path(&current_map.0) {
+            Some(path) => path.path().to_string_lossy().into_owned(),
+            None => {
+                warn!("Not recording battle on a map that wasn't loaded from a file");
+                *replay = Replay::Off;
+                return;
+            }
+        };
+
+        *replay = Replay::Recording(ReplayFile::new(rng.seed(), map));
+    }
+
+    /// Lets the replay's next command through once everything has finished moving and
+    /// attacking. Once every command has been played the replay ends and control passes
+    /// back to the player.
+    ///
+    /// `InputAction::ReplayPause` pauses and resumes, `ReplayStep` steps forward one command
+    /// while paused, and `ReplayFaster` and `ReplaySlower` change the playback speed.
+    pub(crate) fn play_commands(
+        time: Res<Time>,
+        actions: Res<Input<InputAction>>,
+        mut game_speed: ResMut<GameSpeed>,
+        mut replay: ResMut<Replay>,
+        queue_query: Query<&ActionQueue>,
+        map_query: Query<(), With<Map>>,
+    ) {
+        let playback = match &mut *replay {
+            Replay::Playing(playback) => playback,
+            _ => return,
+        };
+
+        if playback.next >= playback.replay.commands.len() {
+            info!("Replay finished");
+            game_speed.speed = GameSpeed::default().speed;
+            *replay = Replay::Off;
+            return;
+        }
+
+        if actions.just_pressed(InputAction::ReplayPause) {
+            playback.paused = !playback.paused;
+        }
+        if actions.just_pressed(InputAction::ReplayFaster) {
+            game_speed.speed = (game_speed.speed * 2.0).min(MAX_PLAYBACK_SPEED);
+        }
+        if actions.just_pressed(InputAction::ReplaySlower) {
+            game_speed.speed = (game_speed.speed / 2.0).max(MIN_PLAYBACK_SPEED);
+        }
+
+        // the map is spawned a frame after deployment starts
+        if map_query.is_empty() || queue_query.iter().any(|queue| !queue.is_idle()) {
+            return;
+        }
+
+        if playback.paused {
+            if !actions.just_pressed(InputAction::ReplayStep) {
+                return;
+            }
+        } else if !playback
+            .delay
+            .tick(time.delta().mul_f32(game_speed.multiplier()))
+            .finished()
+        {
+            return;
+        }
+
+        playback.due = Some(playback.replay.commands[playback.next].clone());
+        playback.next += 1;
+        playback.delay.reset();
+    }
+
+    /// Writes out the battle that's just been recorded, or ends playback
+    fn finish(mut replay: ResMut<Replay>, mut game_speed: ResMut<GameSpeed>) {
+        match &*replay {
+            Replay::Recording(file) => match write_versioned(file, Path::new(REPLAY_PATH)) {
+                Ok(()) => info!("Recorded battle to {}", REPLAY_PATH),
+                Err(error) => error!("Couldn't record battle: {}", error),
+            },
+            Replay::Playing(_) => game_speed.speed = GameSpeed::default().speed,
+            Replay::Off => (),
+        }
+
+        *replay = Replay::Off;
+    }
+}
+
+pub fn replay_exists() -> bool {
+    Path::new(REPLAY_PATH).exists()
+}
@@ -0,0 +1,145 @@
+use bevy::prelude::*;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::{replay::Replay, state::AppState};
+
+/// Set this environment variable to a number to play every battle from that seed, e.g. to
+/// reproduce a bug
+const SEED_VAR: &str = "ZAMLOCK_SEED";
+
+/// Every source of randomness in a battle, seeded once so that the battle can be reproduced
+///
+/// Randomness that only changes how things look (e.g. which grass sprite a tile gets) comes
+/// from a separate stream to randomness that affects the outcome of the battle, so that
+/// cosmetic changes can't knock gameplay out of sync.
+pub struct GameRng {
+    seed: u64,
+    cosmetic: ChaCha8Rng,
+    gameplay: ChaCha8Rng,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> GameRng {
+        let mut cosmetic = ChaCha8Rng::seed_from_u64(seed);
+        cosmetic.set_stream(0);
+        let mut gameplay = ChaCha8Rng::seed_from_u64(seed);
+        gameplay.set_stream(1);
+
+        GameRng {
+            seed,
+            cosmetic,
+            gameplay,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// For things that only affect how the game looks
+    pub fn cosmetic(&mut self) -> &mut ChaCha8Rng {
+        &mut self.cosmetic
+    }
+
+    /// For anything that affects the outcome of a battle, e.g. hit chances or AI decisions
+    pub fn gameplay(&mut self) -> &mut ChaCha8Rng {
+        &mut self.gameplay
+    }
+
+    /// How far through its sequence the gameplay stream is, for saving a battle in progress
+    pub fn gameplay_position(&self) -> u128 {
+        self.gameplay.get_word_pos()
+    }
+
+    /// Restores a saved battle's randomness from its seed and `gameplay_position`
+    pub fn restore(seed: u64, gameplay_position: u128) -> GameRng {
+        let mut rng = GameRng::new(seed);
+        rng.gameplay.set_word_pos(gameplay_position);
+        rng
+    }
+}
+
+impl Default for GameRng {
+    fn default() -> Self {
+        GameRng::new(0)
+    }
+}
+
+/// Seed to play the next battle with, e.g. when simulating battles. Taken when the battle
+/// starts.
+#[derive(Default)]
+pub struct NextBattleSeed(pub Option<u64>);
+
+pub(crate) struct RngPlugin;
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(GameRng::default())
+            .init_resource::<NextBattleSeed>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::Deployment)
+                    .with_system(Self::seed_battle.label("seed_rng")),
+            );
+    }
+}
+
+impl RngPlugin {
+    /// Picks a fresh seed for each battle, unless a replay is being played or one has been
+    /// set with `NextBattleSeed` or `ZAMLOCK_SEED`
+    fn seed_battle(
+        mut rng: ResMut<GameRng>,
+        replay: Res<Replay>,
+        mut next_seed: ResMut<NextBattleSeed>,
+    ) {
+        let seed = replay
+            .seed()
+            .or_else(|| next_seed.0.take())
+            .or_else(|| std::env::var(SEED_VAR).ok()?.parse().ok())
+            .unwrap_or_else(rand::random);
+
+        info!("Battle seed: {}", seed);
+        *rng = GameRng::new(seed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn restore_continues_gameplay_stream() {
+        let mut rng = GameRng::new(42);
+        for _ in 0..7 {
+            rng.gameplay().gen::<u32>();
+        }
+        // cosmetic draws mustn't affect where the gameplay stream is
+        rng.cosmetic().gen::<u64>();
+
+        let mut restored = GameRng::restore(rng.seed(), rng.gameplay_position());
+
+        let expected = (0..16).map(|_| rng.gameplay().gen()).collect::<Vec<u64>>();
+        let actual = (0..16)
+            .map(|_| restored.gameplay().gen())
+            .collect::<Vec<u64>>();
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn restore_at_start_matches_new() {
+        let mut rng = GameRng::new(7);
+        let mut restored = GameRng::restore(7, 0);
+
+        assert_eq!(
+            rng.gameplay().gen::<u64>(),
+            restored.gameplay().gen::<u64>()
+        );
+        assert_eq!(
+            rng.cosmetic().gen::<u64>(),
+            restored.cosmetic().gen::<u64>()
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use std::path::Path;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    controls::InputAction,
+    objectives::{Objective, Objectives},
+    rng::GameRng,
+    state::{AppState, BattleOutcome},
+    tile_map::{Map, Pos, Tile, TileDefinition},
+    turns::TurnState,
+    units::{
+        Ability, ActionQueue, Direction, Faction, MovementProfile, Unit, UnitClass, UnitClasses,
+        UnitName,
+    },
+    versioned_file::{read_versioned, write_versioned, VersionedFile},
+};
+
+/// Where the suspended battle is saved, relative to the working directory
+pub const SAVE_PATH: &str = "saves/battle.ron";
+
+/// A unit's state as it's written in a save file
+#[derive(Deserialize, Serialize)]
+struct SavedUnit {
+    class: String,
+    name: Option<String>,
+    pos: Pos,
+    facing: Direction,
+    faction: Faction,
+    hp: u32,
+    max_hp: u32,
+    attack: u32,
+    move_speed: f32,
+    move_distance: usize,
+    attack_range: usize,
+    movement: MovementProfile,
+    abilities: Vec<Ability>,
+    has_moved: bool,
+    has_acted: bool,
+}
+
+/// A battle in progress. Tiles and units are stored by `Pos` rather than by `Entity`, since
+/// entities are re-spawned (with new ids) when the battle is loaded.
+#[derive(Deserialize, Serialize)]
+struct SaveFile {
+    version: u32,
+    tiles: Vec<TileDefinition>,
+    units: Vec<SavedUnit>,
+    turn: u32,
+    phase: Faction,
+    objectives: Vec<Objective>,
+    seed: u64,
+    /// how far through the gameplay random number stream the battle had got
+    rng_position: u128,
+}
+
+impl VersionedFile for SaveFile {
+    const VERSION: u32 = 2;
+    const NAME: &'static str = "save file";
+}
+
+/// A save file that's been read, waiting to be spawned when the battle starts
+#[derive(Default)]
+struct PendingLoad(Option<SaveFile>);
+
+pub fn save_exists() -> bool {
+    Path::new(SAVE_PATH).exists()
+}
+
+pub(crate) struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PendingLoad::default())
+            .add_system_set(SystemSet::on_update(AppState::Battle).with_system(Self::save_battle))
+            .add_system_set(
+                SystemSet::on_update(AppState::TitleMenu).with_system(Self::load_battle),
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::Battle).with_system(Self::spawn_saved_battle),
+            );
+    }
+}
+
+impl SavePlugin {
+    /// Saves the battle on `InputAction::QuickSave`, as long as it's the player's phase and nothing
+    /// is in the middle of moving or attacking
+    fn save_battle(
+        actions: Res<Input<InputAction>>,
+        turn_state: Res<TurnState>,
+        objectives: Res<Objectives>,
+        rng: Res<GameRng>,
+        map_query: Query<&Map>,
+        tile_query: Query<&Tile>,
+        unit_query: Query<(&Unit, Option<&UnitName>, &ActionQueue)>,
+    ) {
+        if !actions.just_pressed(InputAction::QuickSave) {
+            return;
+        }
+
+        if turn_state.phase != Faction::Player
+            || unit_query.iter().any(|(_, _, queue)| !queue.is_idle())
+        {
+            warn!("Can only save between actions on the player's phase");
+            return;
+        }
+
+        let map = map_query.get_single().expect("Not exactly one map");
+
+        let mut tiles = map
+            .tiles()
+            .filter_map(|(_, entity)| tile_query.get(entity).ok())
+            .map(|tile| TileDefinition {
+                pos: tile.pos,
+                height: tile.height,
+                terrain: tile.terrain,
+            })
+            .collect::<Vec<TileDefinition>>();
+        // keep the file stable between saves of the same map
+        tiles.sort_by_key(|tile| tile.pos);
+
+        let units = unit_query
+            .iter()
+            .map(|(unit, name, _)| SavedUnit {
+                class: unit.class.clone(),
+                name: name.map(|name| name.0.clone()),
+                pos: unit.pos,
+                facing: unit.facing,
+                faction: unit.faction,
+                hp: unit.hp,
+                max_hp: unit.max_hp,
+                attack: unit.attack,
+                move_speed: unit.move_speed,
+                move_distance: unit.move_distance,
+                attack_range: unit.attack_range,
+                movement: unit.movement,
+                abilities: unit.abilities.clone(),
+                has_moved: unit.has_moved,
+                has_acted: unit.has_acted,
+            })
+            .collect();
+
+        let save = SaveFile {
+            version: SaveFile::VERSION,
+            tiles,
+            units,
+            turn: turn_state.turn,
+            phase: turn_state.phase,
+            objectives: objectives.0.clone(),
+            seed: rng.seed(),
+            rng_position: rng.gameplay_position(),
+        };
+
+        match write_versioned(&save, Path::new(SAVE_PATH)) {
+            Ok(()) => info!("Saved battle to {}", SAVE_PATH),
+            Err(error) => error!("Couldn't save battle: {}", error),
+        }
+    }
+
+    /// Resumes the saved battle on `InputAction::LoadSave` on the title menu
+    #[allow(clippy::too_many_arguments)]
+    fn load_battle(
+        mut actions: ResMut<Input<InputAction>>,
+        mut state: ResMut<State<AppState>>,
+        mut pending_load: ResMut<PendingLoad>,
+        mut turn_state: ResMut<TurnState>,
+        mut objectives: ResMut<Objectives>,
+        mut outcome: ResMut<BattleOutcome>,
+        mut rng: ResMut<GameRng>,
+        asset_server: Res<AssetServer>,
+        unit_classes: Res<UnitClasses>,
+    ) {
+        if !unit_classes.is_loaded(&asset_server)
+            || !actions.clear_just_pressed(InputAction::LoadSave)
+        {
+            return;
+        }
+
+        let save = match read_versioned::<SaveFile>(Path::new(SAVE_PATH)) {
+            Ok(save) => save,
+            Err(error) => {
+                error!("Couldn't load battle: {}", error);
+                return;
+            }
+        };
+
+        // set up everything that doesn't need spawning straight away, so that it's ready
+        // when the battle starts
+        *turn_state = TurnState {
+            turn: save.turn,
+            phase: save.phase,
+        };
+        objectives.0 = save.objectives.clone();
+        *rng = GameRng::restore(save.seed, save.rng_position);
+        *outcome = BattleOutcome::Undecided;
+        pending_load.0 = Some(save);
+
+        state.set(AppState::Battle).expect("Couldn't resume battle");
+    }
+
+    fn spawn_saved_battle(
+        mut commands: Commands,
+        mut pending_load: ResMut<PendingLoad>,
+        unit_classes: Res<UnitClasses>,
+        classes: Res<Assets<UnitClass>>,
+    ) {
+        let save = match pending_load.0.take() {
+            Some(save) => save,
+            // started from deployment rather than a save
+            None => return,
+        };
+
+        let map = Map::from_tiles(&mut commands, &save.tiles);
+
+        for saved in save.units {
+            let class = unit_classes
+                .get(&saved.class, &classes)
+                .expect("No such unit class");
+
+            let unit = Unit {
+                class: saved.class,
+                pos: saved.pos,
+                // set from `pos` when spawning
+                tile: map.entity,
+                faction: saved.faction,
+                hp: saved.hp,
+                max_hp: saved.max_hp,
+                attack: saved.attack,
+                move_speed: saved.move_speed,
+                move_distance: saved.move_distance,
+                attack_range: saved.attack_range,
+                movement: saved.movement,
+                abilities: saved.abilities,
+                facing: saved.facing,
+                has_moved: saved.has_moved,
+                has_acted: saved.has_acted,
+            };
+
+            let entity = unit.respawn(&mut commands, &map, class);
+            if let Some(name) = saved.name {
+                commands.entity(entity).insert(UnitName(name));
+            }
+        }
+
+        map.spawn(&mut commands);
+    }
+}
@@ -0,0 +1,470 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Write},
+};
+
+use bevy::{
+    asset::{AssetPlugin, LoadState},
+    input::InputPlugin,
+    prelude::*,
+};
+use rand::prelude::SliceRandom;
+
+use crate::{
+    command::{BattleCommand, CommandEvent},
+    game_speed::GameSpeed,
+    rng::{GameRng, NextBattleSeed},
+    state::{AppState, BattleOutcome},
+    tile_map::{CurrentMap, Map, MapDefinition},
+    turns::TurnState,
+    units::{AiControlled, Faction, Unit, UnitClasses, UnitDamagedEvent},
+    GameplayPlugins,
+};
+
+/// How quickly simulated battles play out. Moves and pauses never take less than a frame,
+/// so at this speed battles run as fast as frames can be updated.
+const SIMULATION_SPEED: f32 = 10_000.0;
+/// Battles that are still going after this many updates are assumed to be stuck, e.g. with
+/// the AI unable to end its phase, and end the simulation
+const MAX_BATTLE_UPDATES: u32 = 200_000;
+
+pub struct SimulationSettings {
+    /// path of the `.map.ron` to fight on, relative to the assets folder
+    pub map: String,
+    pub battles: u32,
+    /// seed for the first battle, with each battle after it using the next seed along
+    pub seed: u64,
+    /// battles that are still undecided after this many turns are counted as draws
+    pub max_turns: u32,
+}
+
+/// A unit class on one side of a battle
+type Side = (Faction, String);
+
+/// How one simulated battle went
+struct BattleResult {
+    /// `None` for a draw
+    winner: Option<Faction>,
+    turns: u32,
+    /// classes that fought on each side
+    sides: BTreeSet<Side>,
+    damage: BTreeMap<Side, u32>,
+}
+
+/// Runs `battles` AI-vs-AI battles one after the other, keeping track of how each goes
+struct Simulation {
+    settings: SimulationSettings,
+    map: Option<Handle<MapDefinition>>,
+    results: Vec<BattleResult>,
+    /// the battle in progress
+    battle: Option<BattleResult>,
+    error: Option<String>,
+    /// why the simulation stopped before playing every battle, with the battles played so
+    /// far still worth reporting
+    stalled: Option<String>,
+}
+
+impl Simulation {
+    fn is_finished(&self) -> bool {
+        self.error.is_some()
+            || self.stalled.is_some()
+            || self.results.len() as u32 >= self.settings.battles
+    }
+}
+
+/// Win rates, turn counts and damage dealt from a batch of simulated battles
+pub struct SimulationReport {
+    results: Vec<BattleResult>,
+    /// set if the simulation stopped early
+    error: Option<String>,
+}
+
+impl SimulationReport {
+    pub fn battles(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Why the simulation stopped before playing every battle
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Writes a row for each class on each side, with how often that side won when the class
+    /// fought for it, how long those battles lasted, and how much damage the class dealt per
+    /// battle. If the simulation stopped early, a last `error` row says why.
+    pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(
+            writer,
+            "faction,class,battles,wins,losses,draws,win_rate,average_turns,damage_per_battle"
+        )?;
+
+        let sides = self
+            .results
+            .iter()
+            .flat_map(|result| result.sides.iter())
+            .collect::<BTreeSet<&Side>>();
+
+        for side in sides {
+            let (faction, class) = side;
+            let results = self
+                .results
+                .iter()
+                .filter(|result| result.sides.contains(side))
+                .collect::<Vec<&BattleResult>>();
+
+            let battles = results.len();
+            let wins = results
+                .iter()
+                .filter(|result| result.winner == Some(*faction))
+                .count();
+            let draws = results
+                .iter()
+                .filter(|result| result.winner.is_none())
+                .count();
+            let turns = results.iter().map(|result| result.turns).sum::<u32>();
+            let damage = results
+                .iter()
+                .filter_map(|result| result.damage.get(side))
+                .sum::<u32>();
+
+            writeln!(
+                writer,
+                "{:?},{},{},{},{},{},{:.3},{:.2},{:.2}",
+                faction,
+                class,
+                battles,
+                wins,
+                battles - wins - draws,
+                draws,
+                wins as f32 / battles as f32,
+                turns as f32 / battles as f32,
+                damage as f32 / battles as f32,
+            )?;
+        }
+
+        if let Some(error) = &self.error {
+            writeln!(writer, "error,\"{}\"", error.replace('"', "\"\""))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Plays `settings.battles` battles with the AI in control of both sides, without a window
+pub fn simulate(settings: SimulationSettings) -> anyhow::Result<SimulationReport> {
+    let simulation = run_simulation(&mut simulation_app(settings))?;
+
+    Ok(SimulationReport {
+        results: simulation.results,
+        error: simulation.stalled,
+    })
+}
+
+fn simulation_app(settings: SimulationSettings) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(AssetPlugin)
+        .add_plugin(InputPlugin)
+        .add_plugins(GameplayPlugins)
+        .insert_resource(AiControlled(vec![Faction::Player, Faction::Enemy]))
+        .insert_resource(GameSpeed {
+            speed: SIMULATION_SPEED,
+            ..default()
+        })
+        .insert_resource(Simulation {
+            settings,
+            map: None,
+            results: vec![],
+            battle: None,
+            error: None,
+            stalled: None,
+        })
+        .add_plugin(SimulationPlugin);
+
+    app
+}
+
+/// Updates `app` until every battle has been played, or a battle gets stuck
+fn run_simulation(app: &mut App) -> anyhow::Result<Simulation> {
+    let mut played = 0;
+    let mut updates = 0;
+
+    while !app.world.resource::<Simulation>().is_finished() {
+        app.update();
+
+        let mut simulation = app.world.resource_mut::<Simulation>();
+        if simulation.results.len() != played {
+            played = simulation.results.len();
+            updates = 0;
+            continue;
+        }
+
+        updates += 1;
+        if updates > MAX_BATTLE_UPDATES {
+            simulation.stalled = Some(format!(
+                "Battle {} (seed {}) didn't finish within {} updates",
+                played + 1,
+                simulation.settings.seed + played as u64,
+                MAX_BATTLE_UPDATES
+            ));
+        }
+    }
+
+    let simulation = app
+        .world
+        .remove_resource::<Simulation>()
+        .expect("Simulation went missing");
+
+    match simulation.error {
+        Some(error) => Err(anyhow::anyhow!(error)),
+        None => Ok(simulation),
+    }
+}
+
+struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(AppState::TitleMenu).with_system(Self::start_battle),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::Deployment)
+                .with_system(Self::deploy_units.before("apply_commands")),
+        )
+        .add_system_set(SystemSet::on_enter(AppState::Battle).with_system(Self::record_sides))
+        .add_system_set(
+            SystemSet::on_update(AppState::Battle)
+                .with_system(Self::record_damage)
+                .with_system(Self::limit_turns.after("evaluate_objectives")),
+        )
+        .add_system_set(SystemSet::on_enter(AppState::Results).with_system(Self::record_result))
+        .add_system_set(SystemSet::on_update(AppState::Results).with_system(Self::next_battle));
+    }
+}
+
+impl SimulationPlugin {
+    /// Starts the next battle, with the next seed, once the map and unit classes have loaded
+    fn start_battle(
+        mut simulation: ResMut<Simulation>,
+        mut state: ResMut<State<AppState>>,
+        mut current_map: ResMut<CurrentMap>,
+        mut next_seed: ResMut<NextBattleSeed>,
+        asset_server: Res<AssetServer>,
+        unit_classes: Res<UnitClasses>,
+    ) {
+        if simulation.is_finished() {
+            return;
+        }
+
+        let map = match &simulation.map {
+            Some(map) => map.clone(),
+            None => {
+                let map = asset_server.load(simulation.settings.map.as_str());
+                simulation.map = Some(map.clone());
+                map
+            }
+        };
+
+        match asset_server.get_load_state(&map) {
+            LoadState::Loaded => (),
+            LoadState::Failed => {
+                simulation.error = Some(format!("Couldn't load {}", simulation.settings.map));
+                return;
+            }
+            _ => return,
+        }
+
+        match unit_classes.load_state(&asset_server) {
+            LoadState::Loaded => (),
+            LoadState::Failed => {
+                simulation.error = Some("Couldn't load unit classes".to_string());
+                return;
+            }
+            _ => return,
+        }
+
+        current_map.0 = map;
+        next_seed.0 = Some(simulation.settings.seed + simulation.results.len() as u64);
+        state
+            .set(AppState::Deployment)
+            .expect("Couldn't start battle");
+    }
+
+    /// Deploys the roster on randomly picked deployment tiles once the map has spawned, then
+    /// begins the battle on the next frame
+    fn deploy_units(
+        mut deployed: Local<bool>,
+        mut rng: ResMut<GameRng>,
+        current_map: Res<CurrentMap>,
+        definitions: Res<Assets<MapDefinition>>,
+        map_query: Query<&Map, Added<Map>>,
+        mut command_events: EventWriter<CommandEvent>,
+    ) {
+        if *deployed {
+            command_events.send(CommandEvent(BattleCommand::BeginBattle));
+            *deployed = false;
+            return;
+        }
+
+        let map = match map_query.get_single() {
+            Ok(map) => map,
+            Err(_) => return,
+        };
+        let definition = definitions
+            .get(&current_map.0)
+            .expect("Map definition not loaded");
+
+        for pos in map
+            .deployment_tiles
+            .choose_multiple(rng.gameplay(), definition.roster.len())
+        {
+            command_events.send(CommandEvent(BattleCommand::Deploy(*pos)));
+        }
+
+        *deployed = true;
+    }
+
+    fn record_sides(mut simulation: ResMut<Simulation>, unit_query: Query<&Unit>) {
+        simulation.battle = Some(BattleResult {
+            winner: None,
+            turns: 0,
+            sides: unit_query
+                .iter()
+                .map(|unit| (unit.faction, unit.class.clone()))
+                .collect(),
+            damage: BTreeMap::new(),
+        });
+    }
+
+    fn record_damage(
+        mut simulation: ResMut<Simulation>,
+        mut events: EventReader<UnitDamagedEvent>,
+        unit_query: Query<&Unit>,
+    ) {
+        let battle = match simulation.battle.as_mut() {
+            Some(battle) => battle,
+            None => return,
+        };
+
+        for UnitDamagedEvent(_, attacker, damage) in events.iter() {
+            if let Ok(attacker) = unit_query.get(*attacker) {
+                *battle
+                    .damage
+                    .entry((attacker.faction, attacker.class.clone()))
+                    .or_default() += damage;
+            }
+        }
+    }
+
+    /// Calls the battle a draw once it goes past the turn limit
+    fn limit_turns(
+        simulation: Res<Simulation>,
+        turn_state: Res<TurnState>,
+        outcome: Res<BattleOutcome>,
+        mut state: ResMut<State<AppState>>,
+    ) {
+        // the objectives decided the battle this frame, and have already ended it
+        if *outcome != BattleOutcome::Undecided {
+            return;
+        }
+
+        if turn_state.turn > simulation.settings.max_turns {
+            // the outcome is left undecided
+            state.set(AppState::Results).expect("Couldn't end battle");
+        }
+    }
+
+    fn record_result(
+        mut simulation: ResMut<Simulation>,
+        outcome: Res<BattleOutcome>,
+        turn_state: Res<TurnState>,
+    ) {
+        let mut battle = match simulation.battle.take() {
+            Some(battle) => battle,
+            None => return,
+        };
+
+        battle.winner = match *outcome {
+            BattleOutcome::Victory => Some(Faction::Player),
+            BattleOutcome::Defeat => Some(Faction::Enemy),
+            BattleOutcome::Undecided => None,
+        };
+        battle.turns = turn_state.turn.min(simulation.settings.max_turns);
+
+        simulation.results.push(battle);
+    }
+
+    fn next_battle(mut state: ResMut<State<AppState>>) {
+        state
+            .set(AppState::TitleMenu)
+            .expect("Couldn't finish battle");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::replay::{Playback, Replay, ReplayFile, ReplayPlugin};
+
+    use super::*;
+
+    const MAP: &str = "maps/skirmish.map.ron";
+    const SEED: u64 = 3;
+
+    fn settings() -> SimulationSettings {
+        SimulationSettings {
+            map: MAP.to_string(),
+            battles: 1,
+            seed: SEED,
+            max_turns: 20,
+        }
+    }
+
+    #[test]
+    fn battles_past_the_turn_limit_are_draws() {
+        let simulation = run_simulation(&mut simulation_app(SimulationSettings {
+            max_turns: 1,
+            ..settings()
+        }))
+        .expect("Couldn't play battle");
+
+        assert_eq!(summary(&simulation).0, None);
+        assert_eq!(summary(&simulation).1, 1);
+    }
+
+    /// Everything about how a battle went that could differ if it was played differently
+    fn summary(simulation: &Simulation) -> (Option<Faction>, u32, BTreeMap<Side, u32>) {
+        let result = simulation.results.first().expect("No battle was played");
+        (result.winner, result.turns, result.damage.clone())
+    }
+
+    /// Anything that changes a battle without going through a `BattleCommand` (other than
+    /// the documented exceptions) would make the replay play out differently
+    #[test]
+    fn replay_plays_out_like_the_original_battle() {
+        let mut app = simulation_app(settings());
+        app.insert_resource(Replay::Recording(ReplayFile::new(SEED, MAP.to_string())));
+        let original = run_simulation(&mut app).expect("Couldn't play battle");
+
+        let file = match app.world.remove_resource::<Replay>() {
+            Some(Replay::Recording(file)) => file,
+            _ => panic!("Battle wasn't recorded"),
+        };
+
+        // nobody is in control but the replay
+        let mut app = simulation_app(settings());
+        app.insert_resource(AiControlled(vec![]))
+            .insert_resource(Replay::Playing(Playback::new(file)))
+            .add_system_set(
+                SystemSet::on_update(AppState::Deployment)
+                    .with_system(ReplayPlugin::play_commands.before("apply_commands")),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Battle)
+                    .with_system(ReplayPlugin::play_commands.before("apply_commands")),
+            );
+        let replayed = run_simulation(&mut app).expect("Couldn't replay battle");
+
+        assert_eq!(summary(&replayed), summary(&original));
+    }
+}
@@ -0,0 +1,39 @@
+use bevy::prelude::*;
+
+/// The screens the game moves between, from the title menu through a battle and back
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AppState {
+    TitleMenu,
+    /// placing the player's units on the map's spawn tiles before the fighting starts
+    Deployment,
+    Battle,
+    /// victory or defeat, see `BattleOutcome`
+    Results,
+}
+
+/// How the most recent battle ended, as decided by its `Objectives`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BattleOutcome {
+    #[default]
+    Undecided,
+    Victory,
+    Defeat,
+}
+
+pub(crate) struct AppStatePlugin;
+
+impl Plugin for AppStatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state(AppState::TitleMenu)
+            .insert_resource(BattleOutcome::default())
+            .add_system_set(
+                SystemSet::on_enter(AppState::Deployment).with_system(Self::reset_outcome),
+            );
+    }
+}
+
+impl AppStatePlugin {
+    fn reset_outcome(mut outcome: ResMut<BattleOutcome>) {
+        *outcome = BattleOutcome::Undecided;
+    }
+}
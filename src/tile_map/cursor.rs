@@ -0,0 +1,108 @@
+use bevy::{input::mouse::MouseMotion, prelude::*};
+
+use crate::{controls::InputAction, units::Direction};
+
+use super::{map::Map, picking::ActiveTile, pos::Pos};
+
+/// How many empty columns the cursor will skip over to reach the next tile
+const MAX_CURSOR_JUMP: i32 = 3;
+
+/// The keyboard and gamepad alternative to pointing at tiles with the mouse: a cursor that
+/// steps from column to column of the map, always landing on the topmost tile.
+///
+/// The `InputAction::Cursor*` actions move it. `ActiveTile` follows the cursor until the
+/// mouse is used again.
+#[derive(Default)]
+pub struct TileCursor {
+    /// the column the cursor is on, with y = 0
+    pub(crate) column: Option<Pos>,
+    /// whether the cursor was used more recently than the mouse
+    pub(crate) active: bool,
+}
+
+pub(crate) struct TileCursorPlugin;
+
+impl Plugin for TileCursorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            Self::move_cursor
+                .label("move_cursor")
+                .after("update_actions"),
+        );
+    }
+}
+
+impl TileCursorPlugin {
+    /// Steps the cursor in the pressed direction, which is relative to the screen rather than
+    /// the map, and hands control back to the mouse when it moves or clicks
+    fn move_cursor(
+        actions: Res<Input<InputAction>>,
+        mouse: Res<Input<MouseButton>>,
+        mut motion_events: EventReader<MouseMotion>,
+        mut cursor: ResMut<TileCursor>,
+        active_tile: Res<ActiveTile>,
+        map_query: Query<&Map>,
+    ) {
+        let map = match map_query.get_single() {
+            Ok(map) => map,
+            Err(_) => {
+                cursor.column = None;
+                return;
+            }
+        };
+
+        if motion_events.iter().count() > 0 || mouse.get_just_pressed().count() > 0 {
+            cursor.active = false;
+        }
+
+        // diagonals on screen, since that's the way the map's axes run
+        let screen_direction = if actions.just_pressed(InputAction::CursorUp) {
+            Direction::NorthWest
+        } else if actions.just_pressed(InputAction::CursorRight) {
+            Direction::NorthEast
+        } else if actions.just_pressed(InputAction::CursorDown) {
+            Direction::SouthEast
+        } else if actions.just_pressed(InputAction::CursorLeft) {
+            Direction::SouthWest
+        } else {
+            return;
+        };
+
+        let was_active = cursor.active;
+        cursor.active = true;
+
+        // pick up from wherever the mouse left off
+        if !was_active || cursor.column.is_none() {
+            let start = active_tile
+                .0
+                .and_then(|entity| map.tiles().find(|(_, tile)| *tile == entity))
+                .or_else(|| map.tiles().min())
+                .map(|(pos, _)| Pos::new(pos.x, 0.0, pos.z));
+
+            cursor.column = start;
+            return;
+        }
+
+        let column = cursor.column.expect("No cursor column");
+        let (x_step, z_step) = match map.rotation.map_direction(screen_direction) {
+            Direction::NorthEast => (1.0, 0.0),
+            Direction::SouthWest => (-1.0, 0.0),
+            Direction::NorthWest => (0.0, 1.0),
+            Direction::SouthEast => (0.0, -1.0),
+        };
+
+        for distance in 1..=MAX_CURSOR_JUMP {
+            let next = Pos::new(
+                column.x + x_step * distance as f32,
+                0.0,
+                column.z + z_step * distance as f32,
+            );
+
+            if map.topmost_tile(next).is_some() {
+                cursor.column = Some(next);
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadState, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{objectives::Objective, units::Faction};
+
+use super::{
+    pos::Pos,
+    tile::{Terrain, TileHeight},
+};
+
+#[derive(Deserialize, Serialize)]
+pub struct TileDefinition {
+    pub pos: Pos,
+    pub height: TileHeight,
+    /// grass unless given
+    #[serde(default)]
+    pub terrain: Terrain,
+}
+
+/// A unit that starts the battle already on the map
+#[derive(Deserialize)]
+pub struct UnitPlacement {
+    /// name of the unit's `UnitClass`
+    pub class: String,
+    pub pos: Pos,
+    pub faction: Faction,
+    /// unique name for objectives to refer to the unit by
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// Everything needed to set up a battle, loaded from a `.map.ron` file
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "8e2b6d41-3f7a-4c9e-b5a0-1d6c9f2e7b84"]
+pub struct MapDefinition {
+    pub name: String,
+    pub tiles: Vec<TileDefinition>,
+    /// tiles the player can place their units on before the battle starts
+    pub deployment_tiles: Vec<Pos>,
+    /// classes of the units the player gets to deploy, in the order they are placed
+    pub roster: Vec<String>,
+    pub units: Vec<UnitPlacement>,
+    pub objectives: Vec<Objective>,
+}
+
+#[derive(Default)]
+pub struct MapDefinitionLoader;
+
+impl AssetLoader for MapDefinitionLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let definition: MapDefinition = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(definition));
+
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["map.ron"]
+    }
+}
+
+/// The map that the next battle is fought on
+#[derive(Default)]
+pub struct CurrentMap(pub Handle<MapDefinition>);
+
+impl CurrentMap {
+    pub fn is_loaded(&self, asset_server: &AssetServer) -> bool {
+        asset_server.get_load_state(&self.0) == LoadState::Loaded
+    }
+}
@@ -1,8 +1,14 @@
-use crate::PreStartupSystemLabels;
+use crate::{
+    camera::MainCamera, controls::InputAction, rng::GameRng, units::ActionQueue,
+    PreStartupSystemLabels,
+};
 use bevy::prelude::*;
-use rand::{prelude::SliceRandom, thread_rng};
+use rand::prelude::SliceRandom;
 
-use super::tile::TileHeight;
+use super::{
+    map::{Map, ViewRotation},
+    tile::{Terrain, Tile, TileHeight},
+};
 
 #[derive(Default)]
 pub(crate) struct MapSprites {
@@ -12,11 +18,11 @@ pub(crate) struct MapSprites {
 }
 
 impl MapSprites {
-    pub(crate) fn get_tile(&self, tile_height: TileHeight) -> Handle<Image> {
+    pub(crate) fn get_tile(&self, tile_height: TileHeight, rng: &mut GameRng) -> Handle<Image> {
         match tile_height {
             TileHeight::Full => self
                 .full_tile
-                .choose(&mut thread_rng())
+                .choose(rng.cosmetic())
                 .expect("no tile sprites")
                 .clone(),
             TileHeight::Half => self.half_tile.clone(),
@@ -32,7 +38,9 @@ impl Plugin for MapGraphicsPlugin {
             .add_startup_system_to_stage(
                 StartupStage::PreStartup,
                 Self::load_graphics.label(PreStartupSystemLabels::LoadGraphics),
-            );
+            )
+            .add_system(Self::add_tile_sprites)
+            .add_system(Self::rotate_view);
     }
 }
 
@@ -56,4 +64,85 @@ impl MapGraphicsPlugin {
         graphics.tile_hover_overlay = tile_hover_handle;
         graphics.half_tile = half_tile_handle;
     }
+
+    /// Gives newly spawned tiles their sprites, picking between the variations of each tile
+    /// at random and tinting them by terrain
+    fn add_tile_sprites(
+        mut commands: Commands,
+        graphics: Res<MapSprites>,
+        mut rng: ResMut<GameRng>,
+        tile_query: Query<(Entity, &Tile), Added<Tile>>,
+    ) {
+        for (entity, tile) in tile_query.iter() {
+            commands
+                .entity(entity)
+                .insert(graphics.get_tile(tile.height, &mut rng))
+                .insert(Sprite {
+                    color: terrain_tint(tile.terrain),
+                    custom_size: Some(Vec2::splat(tile.size)),
+                    ..default()
+                })
+                .insert(Visibility::default())
+                .insert(ComputedVisibility::default());
+        }
+    }
+
+    /// Turns the view a quarter turn either way, keeping the camera over the same part of the
+    /// map. Waits for everything to stop moving, since moves are laid out on screen as they
+    /// start.
+    fn rotate_view(
+        actions: Res<Input<InputAction>>,
+        mut map_query: Query<&mut Map>,
+        mut tile_query: Query<&mut Transform, With<Tile>>,
+        mut camera_query: Query<&mut Transform, (With<MainCamera>, Without<Tile>)>,
+        queue_query: Query<&ActionQueue>,
+    ) {
+        let rotation = |rotation: ViewRotation| {
+            if actions.just_pressed(InputAction::RotateClockwise) {
+                Some(rotation.clockwise())
+            } else if actions.just_pressed(InputAction::RotateAnticlockwise) {
+                Some(rotation.anticlockwise())
+            } else {
+                None
+            }
+        };
+
+        if queue_query.iter().any(|queue| !queue.is_idle()) {
+            return;
+        }
+
+        let mut map = match map_query.get_single_mut() {
+            Ok(map) => map,
+            Err(_) => return,
+        };
+
+        let rotation = match rotation(map.rotation) {
+            Some(rotation) => rotation,
+            None => return,
+        };
+
+        let mut camera_transform = camera_query.single_mut();
+        let centre = map.screen_pos_to_world_pos(camera_transform.translation.truncate());
+
+        map.rotation = rotation;
+
+        for (pos, entity) in map.tiles() {
+            if let Ok(mut transform) = tile_query.get_mut(entity) {
+                transform.translation = map.world_pos_to_screen_pos(pos);
+            }
+        }
+
+        let centre = map.world_pos_to_unit_screen_pos_absolute(centre);
+        camera_transform.translation.x = centre.x;
+        camera_transform.translation.y = centre.y;
+    }
+}
+
+/// Colour to multiply the grass tile sprites by to show `terrain`, until it has its own
+fn terrain_tint(terrain: Terrain) -> Color {
+    match terrain {
+        Terrain::Grass => Color::WHITE,
+        Terrain::Forest => Color::rgb(0.45, 0.7, 0.45),
+        Terrain::Marsh => Color::rgb(0.6, 0.65, 0.8),
+    }
 }
@@ -3,12 +3,59 @@ use std::collections::HashMap;
 use bevy::prelude::*;
 use nalgebra::{Matrix1x2, Matrix2};
 
+use crate::{units::Direction, TILE_SIZE};
+
 use super::{
-    graphics::MapSprites,
+    definition::TileDefinition,
     pos::{Pos, UnorderedPos},
-    tile::Tile,
+    tile::{Terrain, Tile},
 };
 
+/// How far the view has been turned from the map's starting orientation, in clockwise quarter
+/// turns
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ViewRotation(u8);
+
+impl ViewRotation {
+    pub fn clockwise(self) -> ViewRotation {
+        ViewRotation((self.0 + 1) % 4)
+    }
+
+    pub fn anticlockwise(self) -> ViewRotation {
+        ViewRotation((self.0 + 3) % 4)
+    }
+
+    /// Turns grid x and z coords into the ones they're drawn at. Whole numbers stay whole, so
+    /// tiles keep lining up with each other.
+    fn rotate(self, x: f32, z: f32) -> (f32, f32) {
+        match self.0 {
+            0 => (x, z),
+            1 => (z, -x),
+            2 => (-x, -z),
+            _ => (-z, x),
+        }
+    }
+
+    fn unrotate(self, x: f32, z: f32) -> (f32, f32) {
+        match self.0 {
+            0 => (x, z),
+            1 => (-z, x),
+            2 => (-x, -z),
+            _ => (z, -x),
+        }
+    }
+
+    /// The way that something facing `direction` on the map faces on screen
+    pub(crate) fn screen_direction(self, direction: Direction) -> Direction {
+        (0..self.0).fold(direction, |direction, _| direction.clockwise())
+    }
+
+    /// The way on the map that points in `direction` on screen
+    pub(crate) fn map_direction(self, direction: Direction) -> Direction {
+        (0..(4 - self.0) % 4).fold(direction, |direction, _| direction.clockwise())
+    }
+}
+
 #[derive(Component, Clone)]
 /// Contains hashmap of all Tiles and provides utilities for
 /// translating between world and screen coordinates etc
@@ -23,7 +70,20 @@ pub struct Map {
     /// y runs groud - sky
     ///
     /// z runs SouthEast - NorthWest
-    pub(crate) tiles: HashMap<Pos, Entity>,
+    tiles: HashMap<Pos, Entity>,
+    /// The same tiles by column, keyed by x and z with y = 0, lowest first. Kept in step
+    /// with `tiles` by `insert_tile` and `remove_tile`.
+    columns: HashMap<Pos, Vec<(Pos, Entity)>>,
+    /// What covers each tile, kept in step with `tiles` so that movement can be planned
+    /// without looking up each `Tile`
+    terrain: HashMap<Pos, Terrain>,
+    /// Lowest and highest y of any tile, so that picking knows how far up to look
+    elevations: Option<(f32, f32)>,
+    /// Tiles the player can place their units on before a battle starts
+    pub(crate) deployment_tiles: Vec<Pos>,
+    /// Which way round the map is drawn. Everything that's positioned on screen by `Pos`
+    /// has to be laid out again when this changes.
+    pub(crate) rotation: ViewRotation,
     /// Positions the map on the screen. This value is important when mapping screen coordinates
     /// to world/grid coordinates
     pub translation: Vec3,
@@ -36,26 +96,114 @@ impl Map {
             tile_size,
             translation,
             tiles: HashMap::new(),
+            columns: HashMap::new(),
+            terrain: HashMap::new(),
+            elevations: None,
+            deployment_tiles: vec![],
+            rotation: ViewRotation::default(),
         }
     }
 
-    pub(crate) fn insert_tile(
-        &mut self,
-        commands: &mut Commands,
-        pos: Pos,
-        tile: Tile,
-        graphics: &Res<MapSprites>,
-    ) {
-        let tile_entity = commands.spawn().id();
-        tile.spawn(
-            tile_entity,
-            commands,
-            graphics,
-            self.world_pos_to_screen_pos(pos),
+    /// Starts a new map with an entity for each of `tiles`. Call `Map::spawn` once it's
+    /// complete.
+    pub(crate) fn from_tiles(commands: &mut Commands, tiles: &[TileDefinition]) -> Map {
+        let mut map = Map::new(
+            commands.spawn().id(),
+            TILE_SIZE,
+            Vec3::new(0.0, -100.0, 0.0),
         );
 
+        for tile_definition in tiles.iter() {
+            let tile = Tile {
+                height: tile_definition.height,
+                size: map.tile_size,
+                pos: tile_definition.pos,
+                terrain: tile_definition.terrain,
+            };
+
+            map.insert_tile(commands, tile_definition.pos, tile);
+        }
+
+        map
+    }
+
+    /// Adds `tile` at `pos`, replacing any tile that's already there
+    pub(crate) fn insert_tile(&mut self, commands: &mut Commands, pos: Pos, tile: Tile) {
+        self.remove_tile(commands, pos);
+
+        let tile_entity = commands.spawn().id();
+        tile.spawn(tile_entity, commands, self.world_pos_to_screen_pos(pos));
+
         commands.entity(self.entity).add_child(tile_entity);
         self.tiles.insert(pos, tile_entity);
+        self.terrain.insert(pos, tile.terrain);
+
+        let column = self.columns.entry(column_of(pos)).or_default();
+        let index = column.partition_point(|(other, _)| other.y < pos.y);
+        column.insert(index, (pos, tile_entity));
+
+        let y = f32::from(pos.y);
+        self.elevations = Some(match self.elevations {
+            Some((lowest, highest)) => (lowest.min(y), highest.max(y)),
+            None => (y, y),
+        });
+    }
+
+    /// Despawns the tile at `pos`, returning its entity if there was one
+    pub(crate) fn remove_tile(&mut self, commands: &mut Commands, pos: Pos) -> Option<Entity> {
+        let tile_entity = self.tiles.remove(&pos)?;
+        self.terrain.remove(&pos);
+        commands.entity(tile_entity).despawn_recursive();
+
+        if let Some(column) = self.columns.get_mut(&column_of(pos)) {
+            column.retain(|(other, _)| *other != pos);
+            if column.is_empty() {
+                self.columns.remove(&column_of(pos));
+            }
+        }
+
+        // only the ends of each column need looking at, and only if the tile was at one of
+        // the extremes
+        let y = f32::from(pos.y);
+        if matches!(self.elevations, Some((lowest, highest)) if y == lowest || y == highest) {
+            self.elevations = self.columns.values().fold(None, |elevations, column| {
+                let (column_lowest, column_highest) = match (column.first(), column.last()) {
+                    (Some((lowest, _)), Some((highest, _))) => {
+                        (f32::from(lowest.y), f32::from(highest.y))
+                    }
+                    _ => return elevations,
+                };
+
+                Some(match elevations {
+                    Some((lowest, highest)) => {
+                        (lowest.min(column_lowest), highest.max(column_highest))
+                    }
+                    None => (column_lowest, column_highest),
+                })
+            });
+        }
+
+        Some(tile_entity)
+    }
+
+    /// Returns the tile at `pos`, if there is one
+    pub(crate) fn tile(&self, pos: Pos) -> Option<Entity> {
+        self.tiles.get(&pos).copied()
+    }
+
+    /// What covers the tile at `pos`, or grass if there isn't one
+    pub(crate) fn terrain(&self, pos: Pos) -> Terrain {
+        self.terrain.get(&pos).copied().unwrap_or_default()
+    }
+
+    /// How much of a unit's `move_distance` it takes to step onto the tile at `pos`
+    pub(crate) fn move_cost(&self, pos: Pos) -> usize {
+        self.terrain(pos).move_cost()
+    }
+
+    /// Every tile on the map, in no particular order
+    pub(crate) fn tiles(&self) -> impl Iterator<Item = (Pos, Entity)> + '_ {
+        self.tiles.iter().map(|(pos, entity)| (*pos, *entity))
     }
 
     /// This should only be called once, in startup. If you call it on the Map you get from a query
@@ -80,7 +228,11 @@ impl Map {
         let screen_pos_matrix = Matrix1x2::new(offset_screen_pos.x, offset_screen_pos.y);
         let world_pos_matrix = screen_pos_matrix * screen_to_world_transform_matrix;
 
-        Pos::new(world_pos_matrix.x.floor(), 0.0, world_pos_matrix.y.floor())
+        let (x, z) = self
+            .rotation
+            .unrotate(world_pos_matrix.x.floor(), world_pos_matrix.y.floor());
+
+        Pos::new(x, 0.0, z)
     }
 
     /// translate grid pos into screen space coords
@@ -91,6 +243,8 @@ impl Map {
     pub(crate) fn world_pos_to_screen_pos(&self, world_pos: Pos) -> Vec3 {
         let world_to_screen_transform_matrix = self.world_to_screen_transform_matrix();
         let UnorderedPos { x, y, z } = world_pos.into();
+        // z-index goes by where the tile is drawn rather than where it is on the map
+        let (x, z) = self.rotation.rotate(x, z);
 
         let pos_as_matrix = Matrix1x2::new(x, z);
         let mut screen_pos = pos_as_matrix * world_to_screen_transform_matrix;
@@ -138,83 +292,334 @@ impl Map {
         coords
     }
 
-    /// Return `Pos` of all existent `Tile`s reachable in one stop from given `Pos`.
+    /// Returns the highest tile in the column at `column`'s x and z, ignoring its y
+    pub(crate) fn topmost_tile(&self, column: Pos) -> Option<(Pos, Entity)> {
+        self.columns
+            .get(&column_of(column))
+            .and_then(|tiles| tiles.last())
+            .copied()
+    }
+
+    /// Returns every tile whose sprite could be drawn over `screen_pos`, in no particular
+    /// order. Only the handful of columns whose sprites can reach that far are looked at.
+    pub(crate) fn tiles_near_screen_pos(&self, screen_pos: Vec2) -> Vec<(Pos, Entity)> {
+        let (lowest, highest) = match self.elevations {
+            Some(elevations) => elevations,
+            None => return vec![],
+        };
+
+        let offset_screen_pos = screen_pos - self.translation.truncate();
+        // a sprite's centre is tile_size / 2 across per step of (x - z), and tile_size / 4 up
+        // per step of (x + z) plus tile_size / 2 per step of y, and it reaches
+        // tile_size / 2 from its centre either way
+        let across = 2.0 * offset_screen_pos.x / self.tile_size;
+        let up = 4.0 * offset_screen_pos.y / self.tile_size;
+
+        let lowest_sum = (up - 2.0 * highest - 2.0).floor() as i32;
+        let highest_sum = (up - 2.0 * lowest + 2.0).ceil() as i32;
+
+        let mut tiles = vec![];
+
+        for difference in (across - 1.0).floor() as i32..=(across + 1.0).ceil() as i32 {
+            // x and z are whole, so their sum and difference are both odd or both even
+            for sum in (lowest_sum..=highest_sum).filter(|sum| (sum - difference) % 2 == 0) {
+                let (x, z) = self.rotation.unrotate(
+                    ((sum + difference) / 2) as f32,
+                    ((sum - difference) / 2) as f32,
+                );
+
+                if let Some(column) = self.columns.get(&Pos::new(x, 0.0, z)) {
+                    tiles.extend(column.iter().copied());
+                }
+            }
+        }
+
+        tiles
+    }
+
+    /// Return `Pos` of all existent `Tile`s reachable in one stop from given `Pos`, climbing
+    /// at most `max_climb` and dropping at most `max_drop` in the y axis.
     ///
-    /// N.B. movement in the y axis happens 0.5 at a time
-    pub fn get_frontier(&self, pos: Pos) -> Vec<Pos> {
+    /// N.B. movement in the y axis happens 0.5 at a time. Tiles at the current elevation
+    /// come first, then higher ones, then lower ones.
+    pub fn get_frontier(&self, pos: Pos, max_climb: f32, max_drop: f32) -> Vec<Pos> {
         let UnorderedPos { x, y, z } = pos.into();
 
-        let mut frontier: Vec<Pos> = vec![];
+        let neighbours = [(1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0)];
 
-        // Current elevation
-        let level_plus_x = Pos::new(x + 1.0, y, z);
-        if self.tiles.get(&level_plus_x).is_some() && !self.is_pos_covered(level_plus_x) {
-            frontier.push(level_plus_x);
+        let mut elevation_changes = vec![0.0];
+        let mut step = 0.5;
+        while step <= max_climb {
+            elevation_changes.push(step);
+            step += 0.5;
         }
-        let level_minus_x = Pos::new(x - 1.0, y, z);
-        if self.tiles.get(&level_minus_x).is_some() && !self.is_pos_covered(level_minus_x) {
-            frontier.push(level_minus_x);
+        let mut step = 0.5;
+        while step <= max_drop {
+            elevation_changes.push(-step);
+            step += 0.5;
         }
 
-        let level_plus_z = Pos::new(x, y, z + 1.0);
-        if self.tiles.get(&level_plus_z).is_some() && !self.is_pos_covered(level_plus_z) {
-            frontier.push(level_plus_z);
-        }
-        let level_minus_z = Pos::new(x, y, z - 1.0);
-        if self.tiles.get(&level_minus_z).is_some() && !self.is_pos_covered(level_minus_z) {
-            frontier.push(level_minus_z);
-        }
+        let mut frontier: Vec<Pos> = vec![];
 
-        // Higher elevation
-        let higher_plus_x = Pos::new(x + 1.0, y + 0.5, z);
-        if self.tiles.get(&higher_plus_x).is_some() && !self.is_pos_covered(higher_plus_x) {
-            frontier.push(higher_plus_x);
-        }
-        let higher_minus_x = Pos::new(x - 1.0, y + 0.5, z);
-        if self.tiles.get(&higher_minus_x).is_some() && !self.is_pos_covered(higher_minus_x) {
-            frontier.push(higher_minus_x);
+        for elevation_change in elevation_changes {
+            for (x_offset, z_offset) in neighbours {
+                let neighbour = Pos::new(x + x_offset, y + elevation_change, z + z_offset);
+
+                if self.tiles.contains_key(&neighbour) && !self.is_pos_covered(neighbour) {
+                    frontier.push(neighbour);
+                }
+            }
         }
 
-        let higher_plus_z = Pos::new(x, y + 0.5, z + 1.0);
-        if self.tiles.get(&higher_plus_z).is_some() && !self.is_pos_covered(higher_plus_z) {
-            frontier.push(higher_plus_z);
+        frontier
+    }
+
+    /// return `true` if there is a `Tile` directly above provided `Pos` on y axis,
+    /// else return `false
+    pub(crate) fn is_pos_covered(&self, pos: Pos) -> bool {
+        self.columns
+            .get(&column_of(pos))
+            .into_iter()
+            .flatten()
+            .any(|(other, _)| other.y > pos.y && other.y <= pos.y + 1.0)
+    }
+}
+
+/// The key for `pos`'s column in `Map::columns`
+fn column_of(pos: Pos) -> Pos {
+    Pos::new(pos.x, 0.0, pos.z)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use bevy::ecs::system::CommandQueue;
+
+    use crate::tile_map::TileHeight;
+
+    use super::*;
+
+    const ROTATIONS: [ViewRotation; 4] = [
+        ViewRotation(0),
+        ViewRotation(1),
+        ViewRotation(2),
+        ViewRotation(3),
+    ];
+    const DIRECTIONS: [Direction; 4] = [
+        Direction::NorthEast,
+        Direction::NorthWest,
+        Direction::SouthEast,
+        Direction::SouthWest,
+    ];
+
+    /// A map of full height tiles at each of `positions`
+    fn test_map(commands: &mut Commands, positions: &[(f32, f32, f32)]) -> Map {
+        let tiles = positions
+            .iter()
+            .map(|pos| TileDefinition {
+                pos: Pos::from(*pos),
+                height: TileHeight::Full,
+                terrain: Terrain::Grass,
+            })
+            .collect::<Vec<_>>();
+
+        Map::from_tiles(commands, &tiles)
+    }
+
+    /// One step on the grid in `direction`
+    fn step(direction: Direction) -> (f32, f32) {
+        match direction {
+            Direction::NorthEast => (1.0, 0.0),
+            Direction::SouthWest => (-1.0, 0.0),
+            Direction::NorthWest => (0.0, 1.0),
+            Direction::SouthEast => (0.0, -1.0),
         }
-        let higher_minus_z = Pos::new(x, y + 0.5, z - 1.0);
-        if self.tiles.get(&higher_minus_z).is_some() && !self.is_pos_covered(higher_minus_z) {
-            frontier.push(higher_minus_z);
+    }
+
+    #[test]
+    fn unrotate_undoes_rotate() {
+        for rotation in ROTATIONS {
+            for (x, z) in [
+                (0.0, 0.0),
+                (1.0, 0.0),
+                (0.0, 1.0),
+                (3.0, -2.0),
+                (-1.5, 4.25),
+            ] {
+                let (rotated_x, rotated_z) = rotation.rotate(x, z);
+                assert_eq!(rotation.unrotate(rotated_x, rotated_z), (x, z));
+
+                let (unrotated_x, unrotated_z) = rotation.unrotate(x, z);
+                assert_eq!(rotation.rotate(unrotated_x, unrotated_z), (x, z));
+            }
         }
+    }
 
-        // Lower elevation
-        let lower_plus_x = Pos::new(x + 1.0, y - 0.5, z);
-        if self.tiles.get(&lower_plus_x).is_some() && !self.is_pos_covered(lower_plus_x) {
-            frontier.push(lower_plus_x);
+    #[test]
+    fn four_turns_come_back_around() {
+        for rotation in ROTATIONS {
+            assert_eq!(
+                rotation.clockwise().clockwise().clockwise().clockwise(),
+                rotation
+            );
+            assert_eq!(rotation.clockwise().anticlockwise(), rotation);
+            assert_eq!(rotation.anticlockwise().clockwise(), rotation);
         }
-        let lower_minus_x = Pos::new(x - 1.0, y - 0.5, z);
-        if self.tiles.get(&lower_minus_x).is_some() && !self.is_pos_covered(lower_minus_x) {
-            frontier.push(lower_minus_x);
+    }
+
+    #[test]
+    fn screen_direction_matches_where_steps_are_drawn() {
+        for rotation in ROTATIONS {
+            for direction in DIRECTIONS {
+                let (x, z) = step(direction);
+                assert_eq!(
+                    rotation.rotate(x, z),
+                    step(rotation.screen_direction(direction)),
+                    "{:?} at {:?}",
+                    direction,
+                    rotation
+                );
+            }
         }
+    }
 
-        let lower_plus_z = Pos::new(x, y - 0.5, z + 1.0);
-        if self.tiles.get(&lower_plus_z).is_some() && !self.is_pos_covered(lower_plus_z) {
-            frontier.push(lower_plus_z);
+    #[test]
+    fn map_direction_undoes_screen_direction() {
+        for rotation in ROTATIONS {
+            for direction in DIRECTIONS {
+                assert_eq!(
+                    rotation.map_direction(rotation.screen_direction(direction)),
+                    direction
+                );
+                assert_eq!(
+                    rotation.screen_direction(rotation.map_direction(direction)),
+                    direction
+                );
+            }
         }
-        let lower_minus_z = Pos::new(x, y - 0.5, z - 1.0);
-        if self.tiles.get(&lower_minus_z).is_some() && !self.is_pos_covered(lower_minus_z) {
-            frontier.push(lower_minus_z);
+    }
+
+    #[test]
+    fn unrotated_view_draws_the_map_as_is() {
+        for direction in DIRECTIONS {
+            assert_eq!(ViewRotation(0).screen_direction(direction), direction);
         }
+        assert_eq!(
+            ViewRotation(1).screen_direction(Direction::NorthEast),
+            Direction::SouthEast
+        );
+    }
 
-        frontier
+    #[test]
+    fn removed_tiles_leave_their_column() {
+        let world = World::new();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        let mut map = test_map(
+            &mut commands,
+            &[(0.0, 0.0, 0.0), (0.0, 1.0, 0.0), (1.0, 3.0, 0.0)],
+        );
+
+        let top = map.tile(Pos::new(1.0, 3.0, 0.0));
+        assert_eq!(map.remove_tile(&mut commands, Pos::new(1.0, 3.0, 0.0)), top);
+        assert_eq!(map.tile(Pos::new(1.0, 3.0, 0.0)), None);
+        assert_eq!(map.topmost_tile(Pos::new(1.0, 0.0, 0.0)), None);
+        assert_eq!(map.elevations, Some((0.0, 1.0)));
+
+        assert!(map.is_pos_covered(Pos::new(0.0, 0.0, 0.0)));
+        map.remove_tile(&mut commands, Pos::new(0.0, 1.0, 0.0));
+        assert!(!map.is_pos_covered(Pos::new(0.0, 0.0, 0.0)));
+        assert_eq!(
+            map.topmost_tile(Pos::new(0.0, 0.0, 0.0))
+                .map(|(pos, _)| pos),
+            Some(Pos::new(0.0, 0.0, 0.0))
+        );
+        assert_eq!(map.elevations, Some((0.0, 0.0)));
+
+        assert_eq!(
+            map.remove_tile(&mut commands, Pos::new(0.0, 1.0, 0.0)),
+            None
+        );
+        map.remove_tile(&mut commands, Pos::new(0.0, 0.0, 0.0));
+        assert_eq!(map.tiles().count(), 0);
+        assert_eq!(map.elevations, None);
     }
 
-    /// return `true` if there is a `Tile` directly above provided `Pos` on y axis,
-    /// else return `false
-    fn is_pos_covered(&self, pos: Pos) -> bool {
-        self.tiles
-            .get(&Pos::new(pos.x, pos.y + 0.5, pos.z))
-            .is_some()
-            || self
-                .tiles
-                .get(&Pos::new(pos.x, pos.y + 1.0, pos.z))
-                .is_some()
+    #[test]
+    fn inserting_over_a_tile_replaces_it() {
+        let world = World::new();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        let mut map = test_map(&mut commands, &[(0.0, 0.0, 0.0), (0.0, 2.0, 0.0)]);
+
+        let pos = Pos::new(0.0, 0.0, 0.0);
+        let old = map.tile(pos);
+        map.insert_tile(
+            &mut commands,
+            pos,
+            Tile {
+                height: TileHeight::Half,
+                size: map.tile_size,
+                pos,
+                terrain: Terrain::Forest,
+            },
+        );
+
+        assert_ne!(map.tile(pos), old);
+        assert_eq!(map.tiles().count(), 2);
+        assert_eq!(map.columns[&pos].len(), 2);
+        assert_eq!(map.terrain(pos), Terrain::Forest);
+        assert_eq!(map.elevations, Some((0.0, 2.0)));
+    }
+
+    #[test]
+    fn tiles_near_screen_pos_include_every_sprite_over_it() {
+        // uneven ground with some columns stacked, from below zero to several tiles up
+        let mut positions = vec![];
+        for x in -3..=3_i32 {
+            for z in -3..=3 {
+                let y = ((x * 3 + z * 5).rem_euclid(7)) as f32 * 0.5 - 1.0;
+                positions.push((x as f32, y, z as f32));
+                if (x + z) % 3 == 0 {
+                    positions.push((x as f32, y + 3.0, z as f32));
+                }
+            }
+        }
+        let world = World::new();
+        let mut queue = CommandQueue::default();
+        let mut map = test_map(&mut Commands::new(&mut queue, &world), &positions);
+        let half_tile = map.tile_size / 2.0;
+
+        for rotation in ROTATIONS {
+            map.rotation = rotation;
+
+            // off the tile grid's lines, so points don't sit exactly on sprite edges
+            for across in -30..=30 {
+                for up in -30..=45 {
+                    let screen_pos = Vec2::new(across as f32 * 4.3, up as f32 * 4.3)
+                        + map.translation.truncate();
+
+                    let near = map
+                        .tiles_near_screen_pos(screen_pos)
+                        .into_iter()
+                        .collect::<HashSet<_>>();
+
+                    for (pos, entity) in map.tiles() {
+                        let centre = map.world_pos_to_screen_pos_absolute(pos).truncate();
+                        let offset = screen_pos - centre;
+                        if offset.x.abs() < half_tile && offset.y.abs() < half_tile {
+                            assert!(
+                                near.contains(&(pos, entity)),
+                                "{:?} is drawn over {} at {:?}",
+                                pos,
+                                screen_pos,
+                                rotation
+                            );
+                        }
+                    }
+                }
+            }
+        }
     }
 }
@@ -1,113 +1,92 @@
-use crate::{PreStartupSystemLabels, TILE_SIZE};
+use crate::state::AppState;
 use bevy::prelude::*;
 
+mod cursor;
+mod definition;
 mod graphics;
 mod map;
+mod overlay;
 mod picking;
 mod pos;
-mod tile;
-
-use graphics::MapSprites;
-use picking::TilePickingPlugin;
-use tile::TileHeight;
+pub(crate) mod tile;
 
+pub use cursor::TileCursor;
+use cursor::TileCursorPlugin;
+use definition::MapDefinitionLoader;
+pub use definition::{CurrentMap, MapDefinition, TileDefinition};
 pub use map::Map;
-pub use picking::{ActiveTile, DeselectUnitEvent, SelectUnitEvent, SetPathEvent};
+pub(crate) use overlay::{spawn_overlay, OverlayLayer};
+use picking::TilePickingPlugin;
+pub use picking::{
+    ActiveTile, DeselectUnitEvent, HoveredUnit, SelectUnitEvent, SetPathEvent, TargetUnitEvent,
+};
 pub use pos::Pos;
-pub use tile::Tile;
+pub use tile::{Tile, TileHeight};
 
 struct TileMapPlugin;
 
 impl Plugin for TileMapPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system_to_stage(
-            StartupStage::PreStartup,
-            Self::spawn_map.after(PreStartupSystemLabels::LoadGraphics),
-        );
+        app.add_asset::<MapDefinition>()
+            .init_asset_loader::<MapDefinitionLoader>()
+            .insert_resource(CurrentMap::default())
+            .insert_resource(ActiveTile(None))
+            .init_resource::<TileCursor>()
+            .add_event::<SelectUnitEvent>()
+            .add_event::<DeselectUnitEvent>()
+            .add_event::<SetPathEvent>()
+            .add_event::<TargetUnitEvent>()
+            .add_startup_system(Self::load_map_definition)
+            .add_system_set(SystemSet::on_enter(AppState::Deployment).with_system(Self::spawn_map))
+            .add_system_set(SystemSet::on_exit(AppState::Results).with_system(Self::despawn_map));
     }
 }
 
 impl TileMapPlugin {
-    fn spawn_map(mut commands: Commands, graphics: Res<MapSprites>) {
-        let mut map = Map::new(
-            commands.spawn().id(),
-            TILE_SIZE,
-            Vec3::new(0.0, -100.0, 0.0),
-        );
-
-        for x in 0..10 {
-            for z in 0..10 {
-                let x = x as f32;
-                let z = z as f32;
-                let pos = Pos::new(x, 0.0, z);
-
-                let tile = Tile {
-                    height: TileHeight::Full,
-                    size: map.tile_size,
-                    pos,
-                };
-
-                map.insert_tile(&mut commands, pos, tile, &graphics);
-            }
-        }
-
-        let pos = Pos::new(0.0, 1.0, 0.0);
-
-        let tile = Tile {
-            height: TileHeight::Full,
-            size: map.tile_size,
-            pos,
-        };
-
-        map.insert_tile(&mut commands, pos, tile, &graphics);
-
-        let pos = Pos::new(1.0, 0.5, 0.0);
-        let tile = Tile {
-            height: TileHeight::Half,
-            size: map.tile_size,
-            pos,
-        };
-
-        map.insert_tile(&mut commands, pos, tile, &graphics);
-
-        let pos = Pos::new(0.0, 1.5, 0.0);
-        let tile = Tile {
-            height: TileHeight::Half,
-            size: map.tile_size,
-            pos,
-        };
-
-        map.insert_tile(&mut commands, pos, tile, &graphics);
-
-        let pos = Pos::new(5.0, 1.0, 5.0);
-        let tile = Tile {
-            height: TileHeight::Full,
-            size: map.tile_size,
-            pos,
-        };
+    fn load_map_definition(asset_server: Res<AssetServer>, mut current_map: ResMut<CurrentMap>) {
+        current_map.0 = asset_server.load("maps/skirmish.map.ron");
+    }
 
-        map.insert_tile(&mut commands, pos, tile, &graphics);
+    fn spawn_map(
+        mut commands: Commands,
+        current_map: Res<CurrentMap>,
+        definitions: Res<Assets<MapDefinition>>,
+    ) {
+        let definition = definitions
+            .get(&current_map.0)
+            .expect("Map definition not loaded");
 
-        let pos = Pos::new(5.0, 0.5, 4.0);
-        let tile = Tile {
-            height: TileHeight::Half,
-            size: map.tile_size,
-            pos,
-        };
+        let mut map = Map::from_tiles(&mut commands, &definition.tiles);
 
-        map.insert_tile(&mut commands, pos, tile, &graphics);
+        map.deployment_tiles = definition.deployment_tiles.clone();
 
         map.spawn(&mut commands);
     }
+
+    fn despawn_map(mut commands: Commands, map_query: Query<Entity, With<Map>>) {
+        for entity in map_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
 }
 
+/// Maps and their tiles, without any graphics
 pub struct TileMapPluginGroup;
 
 impl PluginGroup for TileMapPluginGroup {
+    fn build(&mut self, group: &mut bevy::app::PluginGroupBuilder) {
+        group.add(TileMapPlugin);
+    }
+}
+
+/// Draws the map and lets the player pick tiles with the mouse, keyboard or a gamepad
+pub struct TileMapGraphicsPluginGroup;
+
+impl PluginGroup for TileMapGraphicsPluginGroup {
     fn build(&mut self, group: &mut bevy::app::PluginGroupBuilder) {
         group
             .add(graphics::MapGraphicsPlugin)
-            .add(TileMapPlugin)
+            .add(TileCursorPlugin)
             .add(TilePickingPlugin);
     }
 }
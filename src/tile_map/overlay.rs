@@ -0,0 +1,52 @@
+use bevy::{ecs::system::EntityCommands, prelude::*};
+
+use super::map::Map;
+
+/// Kinds of sprite drawn on top of a tile, lowest first. Each has its own z so that overlays
+/// on the same tile are always drawn in the same order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum OverlayLayer {
+    ValidMove,
+    Objective,
+    Deployment,
+    DangerZone,
+    Hover,
+    PathPreview,
+}
+
+impl OverlayLayer {
+    /// z relative to the tile, below the 0.009999 that units are raised by to stand on it
+    fn z(self) -> f32 {
+        match self {
+            OverlayLayer::ValidMove => 0.0001,
+            OverlayLayer::Objective => 0.0002,
+            OverlayLayer::Deployment => 0.0003,
+            OverlayLayer::DangerZone => 0.0004,
+            OverlayLayer::Hover => 0.001,
+            OverlayLayer::PathPreview => 0.002,
+        }
+    }
+}
+
+/// Spawns `sprite` over the top face of the tile `tile_entity`, on `layer`, as a child of
+/// the tile so that it moves with it
+pub(crate) fn spawn_overlay<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    map: &Map,
+    tile_entity: Entity,
+    layer: OverlayLayer,
+    texture: Handle<Image>,
+    sprite: Sprite,
+) -> EntityCommands<'w, 's, 'a> {
+    let overlay = commands
+        .spawn_bundle(SpriteBundle {
+            texture,
+            sprite,
+            transform: Transform::from_xyz(0.0, map.tile_top_y_offset(), layer.z()),
+            ..default()
+        })
+        .id();
+
+    commands.entity(tile_entity).add_child(overlay);
+    commands.entity(overlay)
+}
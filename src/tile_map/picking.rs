@@ -1,79 +1,124 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, render::render_resource::TextureFormat};
 
 use crate::{
     camera::{mouse_pos_to_screen_pos, MainCamera},
-    units::{SelectedUnit, Unit, ValidMove},
+    command::{BattleCommand, CommandEvent},
+    controls::InputAction,
+    state::AppState,
+    turns::TurnState,
+    units::{
+        Ability, Action, ActionQueue, Faction, SelectMode, SelectedUnit, Unit, ValidMove, Waypoints,
+    },
 };
 
-use super::{graphics::MapSprites, map::Map, pos::Pos, tile::Tile};
+use super::{
+    cursor::TileCursor,
+    graphics::MapSprites,
+    map::Map,
+    overlay::{spawn_overlay, OverlayLayer},
+    pos::Pos,
+    tile::{Tile, TileFace},
+};
 
 pub(crate) struct TilePickingPlugin;
 
 pub struct ActiveTile(pub Option<Entity>);
+/// The unit standing on the `ActiveTile`, if any
+pub struct HoveredUnit(pub Option<Entity>);
 pub struct SelectUnitEvent(pub Entity);
 pub struct DeselectUnitEvent(pub Entity);
-pub struct SetPathEvent(pub Entity, pub Pos);
+/// Move a unit along a route, given as every `Pos` it will step on (excluding its current one)
+pub struct SetPathEvent(pub Entity, pub Vec<Pos>);
+/// The selected unit (first) has been pointed at another unit (second), e.g. to attack it
+pub struct TargetUnitEvent(pub Entity, pub Entity);
 
 #[derive(Component)]
 struct Highlight;
 
 impl Plugin for TilePickingPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(ActiveTile(None))
-            .add_event::<SelectUnitEvent>()
-            .add_event::<DeselectUnitEvent>()
-            .add_event::<SetPathEvent>()
-            .add_system_to_stage(CoreStage::PreUpdate, Self::set_active_tile)
+        app.insert_resource(HoveredUnit(None))
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                Self::set_active_tile.after("move_cursor"),
+            )
             .add_system_to_stage(
                 CoreStage::PreUpdate,
                 Self::hover_tile.after(Self::set_active_tile),
             )
-            .add_system(Self::click_tile.label("click_tile"));
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                Self::hover_unit.after(Self::set_active_tile),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Battle)
+                    .with_system(
+                        Self::click_tile
+                            .label("click_tile")
+                            .before("apply_commands"),
+                    )
+                    .with_system(Self::cancel_selection.before("apply_commands")),
+            );
     }
 }
 
 impl TilePickingPlugin {
     /// Populates `ActiveTile` resource with the entity for the tile that the mouse is hovering ove (if any).
+    /// Hovering a unit's sprite makes the tile it stands on active, even where the sprite
+    /// reaches over other tiles, unless a tile drawn in front of the unit covers it there.
+    ///
+    /// While the `TileCursor` is in use, it's the topmost tile under the cursor instead.
+    #[allow(clippy::too_many_arguments)]
     fn set_active_tile(
         wnds: Res<Windows>,
         q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
         mut active_tile: ResMut<ActiveTile>,
+        cursor: Res<TileCursor>,
         map_query: Query<&Map>,
+        tile_query: Query<&Tile>,
+        unit_sprite_query: UnitSpriteQuery,
+        atlases: Res<Assets<TextureAtlas>>,
+        images: Res<Assets<Image>>,
     ) {
-        let map = map_query.get_single().expect("No map!");
         let mut new_active_tile: ActiveTile = ActiveTile(None);
 
-        if let Some(screen_pos) = mouse_pos_to_screen_pos(wnds, q_camera) {
-            let mut picked: Option<(Pos, Entity)> = None;
-
-            // sort tiles highest elevation first
-            let mut tiles = map.tiles.iter().collect::<Vec<(&Pos, &Entity)>>();
-            tiles.sort_by(|(point_a, _), (point_b, _)| point_b.y.cmp(&point_a.y));
-
-            for (pos, entity) in tiles.iter() {
-                if picked.is_some() {
-                    break;
-                }
+        // no map outside of battles
+        let map = match map_query.get_single() {
+            Ok(map) => map,
+            Err(_) => {
+                *active_tile = new_active_tile;
+                return;
+            }
+        };
 
-                // translate mouse pos to ground level grid coord and pick current tile
-                // if it matches on x and z coords
-                let y_offset = map.tile_height() * f32::from(pos.y);
-                let offset_screen_pos = Vec2::new(screen_pos.x, screen_pos.y - y_offset);
-                let offset_world_pos = map.screen_pos_to_world_pos(offset_screen_pos);
+        if cursor.active {
+            let picked = cursor
+                .column
+                .and_then(|column| map.topmost_tile(column))
+                .map(|(_, entity)| entity);
 
-                if pos.x == offset_world_pos.x && pos.z == offset_world_pos.z {
-                    picked = Some((**pos, **entity));
-                }
+            // avoid flagging the resource as changed when the cursor hasn't moved
+            if active_tile.0 != picked {
+                *active_tile = ActiveTile(picked);
             }
+            return;
+        }
 
-            if let Some((picked_pos, picked_entity)) = picked {
-                // can't pick tiles that are underneath others
-                if !tiles.iter().any(|(pos, _)| {
-                    pos.x == picked_pos.x && pos.z == picked_pos.z && pos.y > picked_pos.y
-                }) {
-                    new_active_tile = ActiveTile(Some(picked_entity));
+        if let Some(screen_pos) = mouse_pos_to_screen_pos(wnds, q_camera) {
+            let unit = pick_unit(&unit_sprite_query, &atlases, &images, screen_pos);
+            let tile = pick_tile(map, &tile_query, screen_pos);
+
+            new_active_tile = ActiveTile(match (unit, tile) {
+                (Some((unit_z, unit_tile)), Some((tile_z, tile_entity, _))) => {
+                    Some(if unit_z >= tile_z {
+                        unit_tile
+                    } else {
+                        tile_entity
+                    })
                 }
-            }
+                (Some((_, unit_tile)), None) => Some(unit_tile),
+                (None, tile) => tile.map(|(_, entity, _)| entity),
+            });
         }
 
         *active_tile = new_active_tile;
@@ -82,7 +127,7 @@ impl TilePickingPlugin {
     /// Adds highlight sprite to `ActiveTile`
     fn hover_tile(
         mut commands: Commands,
-        tile_query: Query<&Tile>,
+        map_query: Query<&Map>,
         highlight_query: Query<Entity, With<Highlight>>,
         graphics: Res<MapSprites>,
         active_tile: ResMut<ActiveTile>,
@@ -95,9 +140,7 @@ impl TilePickingPlugin {
         }
 
         let tile_entity = active_tile.0.expect("No active tile entity");
-        let tile = tile_query
-            .get(tile_entity)
-            .expect("No tile for active tile entity");
+        let map = map_query.get_single().expect("No map for active tile");
 
         if let Ok(highlight) = highlight_query.get_single() {
             if highlight == tile_entity {
@@ -107,33 +150,98 @@ impl TilePickingPlugin {
             }
         }
 
-        let highlight = commands
-            .spawn_bundle(SpriteBundle {
-                texture: graphics.tile_hover_overlay.clone(),
-                transform: Transform::from_xyz(0.0, tile.size / 4.0, 0.001),
-                ..default()
-            })
-            .insert(Highlight)
-            .id();
+        spawn_overlay(
+            &mut commands,
+            map,
+            tile_entity,
+            OverlayLayer::Hover,
+            graphics.tile_hover_overlay.clone(),
+            default(),
+        )
+        .insert(Highlight);
+    }
+
+    /// Keeps `HoveredUnit` up to date with the `ActiveTile`
+    fn hover_unit(
+        active_tile: Res<ActiveTile>,
+        mut hovered_unit: ResMut<HoveredUnit>,
+        unit_query: Query<(Entity, &Unit)>,
+    ) {
+        let hovered = active_tile.0.and_then(|tile_entity| {
+            unit_query
+                .iter()
+                .find(|(_, unit)| unit.tile == tile_entity)
+                .map(|(entity, _)| entity)
+        });
+
+        // avoid flagging the resource as changed when nothing has
+        if hovered_unit.0 != hovered {
+            hovered_unit.0 = hovered;
+        }
+    }
+
+    /// Steps back one choice on `InputAction::Cancel`: backs out of the selected unit's
+    /// `SelectMode` to the previous one, or once it's choosing where to move, takes back the
+    /// last of its `Waypoints`, and once there are none, deselects it
+    fn cancel_selection(
+        actions: Res<Input<InputAction>>,
+        mut selected_unit: ResMut<SelectedUnit>,
+        mut waypoints: ResMut<Waypoints>,
+        mut command_events: EventWriter<CommandEvent>,
+    ) {
+        if !actions.just_pressed(InputAction::Cancel) {
+            return;
+        }
+
+        let (entity, mode) = match *selected_unit {
+            SelectedUnit::Some { entity, mode } => (entity, mode),
+            SelectedUnit::None => return,
+        };
 
-        commands.entity(tile_entity).add_child(highlight);
+        if let Some(previous) = mode.previous() {
+            *selected_unit = SelectedUnit::Some {
+                entity,
+                mode: previous,
+            };
+        } else if waypoints.0.pop().is_none() {
+            command_events.send(CommandEvent(BattleCommand::DeselectUnit));
+        }
     }
 
-    /// Send TileClickEvent when click occurs
-    /// while there is an `ActiveTile`
+    /// Send TileClickEvent on `InputAction::Confirm`
+    /// while there is an `ActiveTile`, and nothing is still moving or attacking
+    ///
+    /// What confirming does with a unit selected depends on its `SelectMode`:
+    /// - `Move`: confirming a valid move moves there, and confirming another of the player's
+    ///   ready units selects it instead. Confirming the selected unit again opens its
+    ///   `ActionMenu`, or deselects it if it has nothing left to do.
+    /// - `ActionMenu`: chooses to attack, which is the only ability so far
+    /// - `AttackTarget`: confirming a unit of another faction attacks it
+    ///
+    /// Anything else leaves the selection alone, so backing out is left to
+    /// `InputAction::Cancel`.
+    ///
+    /// Confirming a valid move with `InputAction::AddWaypoint` held adds it to the selected
+    /// unit's `Waypoints` (or removes it if it's already one) instead of moving straight there
     // TODO: handle all clicks from here by sending different events depending on whether
     // there is a SelectedUnit, ActiveTile etc
+    #[allow(clippy::too_many_arguments)]
     fn click_tile(
         active_tile: Res<ActiveTile>,
-        selected_unit: Res<SelectedUnit>,
-        mouse: Res<Input<MouseButton>>,
-        mut select_events: EventWriter<SelectUnitEvent>,
-        mut deselect_events: EventWriter<DeselectUnitEvent>,
-        mut set_path_events: EventWriter<SetPathEvent>,
+        mut selected_unit: ResMut<SelectedUnit>,
+        actions: Res<Input<InputAction>>,
+        turn_state: Res<TurnState>,
+        mut waypoints: ResMut<Waypoints>,
+        mut command_events: EventWriter<CommandEvent>,
+        mut target_events: EventWriter<TargetUnitEvent>,
         unit_query: Query<(Entity, &Unit)>,
+        queue_query: Query<&ActionQueue>,
         valid_move_query: Query<(&Tile, Option<&ValidMove>)>,
+        map_query: Query<&Map>,
     ) {
-        if !mouse.just_pressed(MouseButton::Left) {
+        if !actions.just_pressed(InputAction::Confirm)
+            || queue_query.iter().any(|queue| !queue.is_idle())
+        {
             return;
         }
 
@@ -141,28 +249,238 @@ impl TilePickingPlugin {
         if let ActiveTile(Some(tile_entity)) = *active_tile {
             match *selected_unit {
                 SelectedUnit::None => {
-                    if let Some((unit_entity, _unit)) =
-                        unit_query.iter().find(|(_e, u)| u.tile == tile_entity)
-                    {
-                        select_events.send(SelectUnitEvent(unit_entity))
+                    // the player can only command their own units, on their own phase
+                    if turn_state.phase != Faction::Player {
+                        return;
+                    }
+
+                    if let Some((_, unit)) = unit_query.iter().find(|(_e, u)| {
+                        u.tile == tile_entity && u.faction == Faction::Player && !u.has_acted
+                    }) {
+                        command_events.send(CommandEvent(BattleCommand::SelectUnit(unit.pos)))
                     }
                 }
                 SelectedUnit::Some {
                     entity: unit_entity,
-                    mode: _,
+                    mode,
                 } => {
+                    let map = map_query.get_single().expect("Not exactly one map");
+                    let (_, unit) = unit_query
+                        .get(unit_entity)
+                        .expect("No entity for selected unit");
                     let (tile, valid_move) = valid_move_query
                         .get(tile_entity)
                         .expect("No tile for selected entity");
 
+                    let confirmed_unit = unit_query.iter().find(|(_e, u)| u.tile == tile_entity);
+
+                    match mode {
+                        SelectMode::Move => {}
+                        SelectMode::ActionMenu => {
+                            *selected_unit = SelectedUnit::Some {
+                                entity: unit_entity,
+                                mode: SelectMode::AttackTarget,
+                            };
+                            return;
+                        }
+                        SelectMode::AttackTarget => {
+                            if let Some((target_entity, target)) = confirmed_unit {
+                                if target.faction != unit.faction {
+                                    target_events.send(TargetUnitEvent(unit_entity, target_entity));
+                                    command_events.send(CommandEvent(BattleCommand::DeselectUnit));
+                                }
+                            }
+                            return;
+                        }
+                    }
+
+                    if let Some((target_entity, target)) = confirmed_unit {
+                        if target_entity == unit_entity {
+                            if unit.can(Ability::Attack) && !unit.has_acted {
+                                *selected_unit = SelectedUnit::Some {
+                                    entity: unit_entity,
+                                    mode: SelectMode::ActionMenu,
+                                };
+                            } else {
+                                command_events.send(CommandEvent(BattleCommand::DeselectUnit));
+                            }
+                        } else if target.faction == unit.faction && !target.has_acted {
+                            command_events
+                                .send(CommandEvent(BattleCommand::SelectUnit(target.pos)));
+                        }
+                        return;
+                    }
+
+                    let adding_waypoint = actions.pressed(InputAction::AddWaypoint);
+
+                    if adding_waypoint {
+                        if let Some(index) = waypoints.0.iter().position(|pos| *pos == tile.pos) {
+                            waypoints.0.truncate(index);
+                        } else if valid_move.is_some()
+                            && unit.get_route(&waypoints.0, tile.pos, map).is_some()
+                        {
+                            waypoints.0.push(tile.pos);
+                        }
+
+                        return;
+                    }
+
                     // Will need to check here for other interaction types in the future
                     if valid_move.is_some() {
-                        set_path_events.send(SetPathEvent(unit_entity, tile.pos))
+                        if let Some(route) = unit.get_route(&waypoints.0, tile.pos, map) {
+                            command_events.send(CommandEvent(BattleCommand::Act(
+                                unit.pos,
+                                vec![Action::Move(route)],
+                            )));
+                            command_events.send(CommandEvent(BattleCommand::DeselectUnit));
+                        }
                     }
-
-                    deselect_events.send(DeselectUnitEvent(unit_entity));
                 }
             }
         }
     }
 }
+
+/// Returns the z of the tile drawn at `screen_pos`, the tile, and which of its faces is
+/// there.
+///
+/// Nearby tiles are tested against the shapes of their sprites, and the one drawn in front
+/// wins, so the side of a raised tile is picked rather than whatever is behind it.
+pub(crate) fn pick_tile(
+    map: &Map,
+    tile_query: &Query<&Tile>,
+    screen_pos: Vec2,
+) -> Option<(f32, Entity, TileFace)> {
+    map.tiles_near_screen_pos(screen_pos)
+        .into_iter()
+        .filter_map(|(pos, entity)| {
+            let centre = map.world_pos_to_screen_pos_absolute(pos);
+            let tile = tile_query.get(entity).ok()?;
+            let face = tile.face_at(screen_pos - centre.truncate(), map.rotation)?;
+
+            Some((centre.z, entity, face))
+        })
+        .max_by(|(z_a, _, _), (z_b, _, _)| z_a.total_cmp(z_b))
+}
+
+type UnitSpriteQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Unit,
+        &'static GlobalTransform,
+        &'static TextureAtlasSprite,
+        &'static Handle<TextureAtlas>,
+    ),
+>;
+
+/// Returns the z of the unit whose sprite is drawn at `screen_pos`, along with the tile
+/// under it, if any.
+///
+/// Each sprite's hitbox is the opaque part of its current frame, placed around the unit by
+/// the sprite's anchor.
+fn pick_unit(
+    unit_query: &UnitSpriteQuery,
+    atlases: &Assets<TextureAtlas>,
+    images: &Assets<Image>,
+    screen_pos: Vec2,
+) -> Option<(f32, Entity)> {
+    unit_query
+        .iter()
+        .filter_map(|(unit, transform, sprite, atlas)| {
+            let atlas = atlases.get(atlas)?;
+            let frame = atlas.textures.get(sprite.index)?;
+            let size =
+                sprite.custom_size.unwrap_or(frame.max - frame.min) * transform.scale.truncate();
+            let min = transform.translation.truncate()
+                - (sprite.anchor.as_vec() + Vec2::splat(0.5)) * size;
+            let max = min + size;
+
+            let in_frame = screen_pos.x >= min.x
+                && screen_pos.x < max.x
+                && screen_pos.y >= min.y
+                && screen_pos.y < max.y;
+            if !in_frame {
+                return None;
+            }
+
+            // where in the frame the point is, as a fraction from its top left
+            let mut offset = (screen_pos - min) / size;
+            offset.y = 1.0 - offset.y;
+            if sprite.flip_x {
+                offset.x = 1.0 - offset.x;
+            }
+            if sprite.flip_y {
+                offset.y = 1.0 - offset.y;
+            }
+
+            let pixel = frame.min + offset * (frame.max - frame.min);
+            let hit = images
+                .get(&atlas.texture)
+                .is_none_or(|image| is_opaque(image, pixel));
+
+            hit.then(|| (transform.translation.z, unit.tile))
+        })
+        .max_by(|(z_a, _), (z_b, _)| z_a.total_cmp(z_b))
+}
+
+/// Whether the pixel at `pixel` in `image` can be seen. Images in formats other than 8 bit
+/// RGBA count as opaque all over.
+fn is_opaque(image: &Image, pixel: Vec2) -> bool {
+    let descriptor = &image.texture_descriptor;
+    if !matches!(
+        descriptor.format,
+        TextureFormat::Rgba8UnormSrgb | TextureFormat::Rgba8Unorm
+    ) {
+        return true;
+    }
+
+    let x = (pixel.x as u32).min(descriptor.size.width.saturating_sub(1));
+    let y = (pixel.y as u32).min(descriptor.size.height.saturating_sub(1));
+    let alpha = (y * descriptor.size.width + x) as usize * 4 + 3;
+
+    image.data.get(alpha).is_none_or(|alpha| *alpha > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::render_resource::{Extent3d, TextureDimension};
+
+    use super::*;
+
+    /// A 2x2 image that's transparent only in its top right pixel
+    fn test_image(format: TextureFormat) -> Image {
+        Image::new(
+            Extent3d {
+                width: 2,
+                height: 2,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            vec![
+                255, 255, 255, 255, 0, 0, 0, 0, //
+                255, 255, 255, 255, 255, 255, 255, 255,
+            ],
+            format,
+        )
+    }
+
+    #[test]
+    fn only_transparent_pixels_are_see_through() {
+        let image = test_image(TextureFormat::Rgba8UnormSrgb);
+
+        assert!(is_opaque(&image, Vec2::new(0.5, 0.5)));
+        assert!(!is_opaque(&image, Vec2::new(1.5, 0.5)));
+        assert!(is_opaque(&image, Vec2::new(0.5, 1.5)));
+        assert!(is_opaque(&image, Vec2::new(1.5, 1.5)));
+        // the far edges of a frame land on its last pixels
+        assert!(!is_opaque(&image, Vec2::new(2.0, 0.0)));
+    }
+
+    #[test]
+    fn other_formats_are_opaque() {
+        let image = test_image(TextureFormat::Bgra8UnormSrgb);
+
+        assert!(is_opaque(&image, Vec2::new(1.5, 0.5)));
+    }
+}
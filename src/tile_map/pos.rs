@@ -1,10 +1,12 @@
 use ordered_float::OrderedFloat;
-
-use super::{Map};
+use serde::{Deserialize, Serialize};
 
 /// Pos uses OrderedFloats so that it can be a key in a hashmap. Implementing Ord will
 /// also be important for pathfinding later.
-#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+///
+/// Written as an `(x, y, z)` tuple in data files.
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(from = "(f32, f32, f32)", into = "(f32, f32, f32)")]
 pub struct Pos {
     pub(crate) x: OrderedFloat<f32>,
     pub(crate) y: OrderedFloat<f32>,
@@ -32,11 +34,21 @@ impl Pos {
         (self.x - other.x).abs() + (self.y - other.y).abs() + (self.z - other.z).abs()
     }
 
-    pub(crate) fn successors(&self, map: &Map) -> Vec<(Pos, u32)> {
-        map.get_frontier(*self)
-            .iter()
-            .map(|pos| (*pos, 1))
-            .collect()
+    /// Distance along the x and z axes only, i.e. ignoring elevation
+    pub(crate) fn ground_distance(&self, other: &Pos) -> f32 {
+        (self.x - other.x).abs() + (self.z - other.z).abs()
+    }
+}
+
+impl From<(f32, f32, f32)> for Pos {
+    fn from((x, y, z): (f32, f32, f32)) -> Self {
+        Pos::new(x, y, z)
+    }
+}
+
+impl From<Pos> for (f32, f32, f32) {
+    fn from(pos: Pos) -> Self {
+        (pos.x.into(), pos.y.into(), pos.z.into())
     }
 }
 
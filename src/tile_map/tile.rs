@@ -1,40 +1,208 @@
-use super::{graphics::MapSprites, Pos};
+use super::{map::ViewRotation, Pos};
+use crate::units::Direction;
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub enum TileHeight {
     Full,
     Half,
 }
 
+/// What covers the top of a tile, which changes how hard it is to cross and how well units
+/// standing on it are protected
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Terrain {
+    #[default]
+    Grass,
+    Forest,
+    Marsh,
+}
+
+impl Terrain {
+    /// How much of a unit's `move_distance` it takes to step onto this terrain
+    pub fn move_cost(self) -> usize {
+        match self {
+            Terrain::Grass => 1,
+            Terrain::Forest => 2,
+            Terrain::Marsh => 3,
+        }
+    }
+
+    /// How much less damage units standing on this terrain take from each attack
+    pub fn defence_bonus(self) -> u32 {
+        match self {
+            Terrain::Grass | Terrain::Marsh => 0,
+            Terrain::Forest => 1,
+        }
+    }
+}
+
+/// Which part of a tile's sprite is under a point on screen
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TileFace {
+    Top,
+    /// one of the two sides facing the camera, along with which way it faces on the map
+    Side(Direction),
+}
+
 #[derive(Component, Copy, Clone, Debug)]
 pub struct Tile {
     pub(crate) height: TileHeight,
     /// height/width in pixels (tile must be square!)
     pub(crate) size: f32,
     pub(crate) pos: Pos,
+    pub(crate) terrain: Terrain,
 }
 
 impl Tile {
+    /// Spawns the tile without any graphics, which `MapGraphicsPlugin` adds when there is
+    /// something to draw them
     pub(crate) fn spawn(
         &self,
         entity: Entity,
         commands: &mut Commands,
-        graphics: &Res<MapSprites>,
         translation: Vec3,
     ) -> Entity {
         commands
             .entity(entity)
-            .insert_bundle(SpriteBundle {
-                texture: graphics.get_tile(self.height),
-                sprite: Sprite {
-                    custom_size: Some(Vec2::splat(self.size)),
-                    ..default()
-                },
-                transform: Transform::from_translation(translation),
-                ..default()
-            })
+            .insert_bundle(TransformBundle::from_transform(
+                Transform::from_translation(translation),
+            ))
             .insert(*self)
             .id()
     }
+
+    /// Returns the face drawn at `offset` from the centre of this tile's sprite, if any.
+    ///
+    /// The top face is a diamond filling the upper half of the sprite, with its bottom corner
+    /// at the centre. The sides hang below it, half a tile deep for full tiles and a quarter
+    /// for half tiles.
+    pub(crate) fn face_at(&self, offset: Vec2, rotation: ViewRotation) -> Option<TileFace> {
+        let half_width = self.size / 2.0;
+        if offset.x.abs() > half_width {
+            return None;
+        }
+
+        // height of the top face's lower edges at this x
+        let top_bottom_edge = offset.x.abs() / 2.0;
+        let top_top_edge = half_width - offset.x.abs() / 2.0;
+        let side_depth = match self.height {
+            TileHeight::Full => self.size / 2.0,
+            TileHeight::Half => self.size / 4.0,
+        };
+
+        if offset.y >= top_bottom_edge && offset.y <= top_top_edge {
+            Some(TileFace::Top)
+        } else if offset.y < top_bottom_edge && offset.y >= top_bottom_edge - side_depth {
+            let screen_direction = if offset.x < 0.0 {
+                Direction::SouthWest
+            } else {
+                Direction::SouthEast
+            };
+
+            Some(TileFace::Side(rotation.map_direction(screen_direction)))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_tile(height: TileHeight) -> Tile {
+        Tile {
+            height,
+            size: 32.0,
+            pos: Pos::new(0.0, 0.0, 0.0),
+            terrain: Terrain::Grass,
+        }
+    }
+
+    fn face_at(tile: Tile, x: f32, y: f32) -> Option<TileFace> {
+        tile.face_at(Vec2::new(x, y), ViewRotation::default())
+    }
+
+    #[test]
+    fn top_face_fills_the_upper_diamond() {
+        let tile = test_tile(TileHeight::Full);
+
+        assert_eq!(face_at(tile, 0.0, 0.0), Some(TileFace::Top));
+        assert_eq!(face_at(tile, 0.0, 8.0), Some(TileFace::Top));
+        assert_eq!(face_at(tile, 0.0, 16.0), Some(TileFace::Top));
+        assert_eq!(face_at(tile, -12.0, 8.0), Some(TileFace::Top));
+        assert_eq!(face_at(tile, 12.0, 8.0), Some(TileFace::Top));
+    }
+
+    #[test]
+    fn sides_hang_below_the_top() {
+        let tile = test_tile(TileHeight::Full);
+
+        assert_eq!(
+            face_at(tile, -8.0, 0.0),
+            Some(TileFace::Side(Direction::SouthWest))
+        );
+        assert_eq!(
+            face_at(tile, 8.0, 0.0),
+            Some(TileFace::Side(Direction::SouthEast))
+        );
+        assert_eq!(
+            face_at(tile, -1.0, -15.0),
+            Some(TileFace::Side(Direction::SouthWest))
+        );
+        assert_eq!(
+            face_at(tile, 1.0, -15.0),
+            Some(TileFace::Side(Direction::SouthEast))
+        );
+    }
+
+    #[test]
+    fn half_tiles_have_shallower_sides() {
+        let full = test_tile(TileHeight::Full);
+        let half = test_tile(TileHeight::Half);
+
+        assert_eq!(
+            face_at(half, -8.0, -3.0),
+            Some(TileFace::Side(Direction::SouthWest))
+        );
+        assert_eq!(face_at(half, -8.0, -5.0), None);
+        assert_eq!(
+            face_at(full, -8.0, -5.0),
+            Some(TileFace::Side(Direction::SouthWest))
+        );
+    }
+
+    #[test]
+    fn points_just_outside_the_sprite_miss() {
+        let tile = test_tile(TileHeight::Full);
+
+        // beyond the left and right corners
+        assert_eq!(face_at(tile, -16.5, 8.0), None);
+        assert_eq!(face_at(tile, 16.5, 8.0), None);
+        // above the top corner, and beside the top face's upper edges
+        assert_eq!(face_at(tile, 0.0, 16.5), None);
+        assert_eq!(face_at(tile, -12.0, 10.5), None);
+        assert_eq!(face_at(tile, 12.0, 10.5), None);
+        // below the bottom corner, and beside the sides' lower edges
+        assert_eq!(face_at(tile, 0.0, -16.5), None);
+        assert_eq!(face_at(tile, -12.0, -10.5), None);
+        assert_eq!(face_at(tile, 12.0, -10.5), None);
+    }
+
+    #[test]
+    fn sides_face_the_way_they_point_on_the_map() {
+        let tile = test_tile(TileHeight::Full);
+        let rotation = ViewRotation::default().clockwise();
+
+        assert_eq!(
+            tile.face_at(Vec2::new(-8.0, 0.0), rotation),
+            Some(TileFace::Side(Direction::SouthEast))
+        );
+        assert_eq!(
+            tile.face_at(Vec2::new(8.0, 0.0), rotation),
+            Some(TileFace::Side(Direction::NorthEast))
+        );
+    }
 }
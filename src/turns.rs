@@ -0,0 +1,133 @@
+use bevy::prelude::*;
+
+use crate::{
+    command::{BattleCommand, CommandEvent},
+    controls::InputAction,
+    state::AppState,
+    tile_map::DeselectUnitEvent,
+    units::{ActionQueue, Faction, PreviousMove, SelectedUnit, Unit},
+};
+
+/// Whose phase it is in the current battle. Each turn is a player phase followed by an
+/// enemy phase.
+pub struct TurnState {
+    pub turn: u32,
+    pub phase: Faction,
+}
+
+impl Default for TurnState {
+    fn default() -> Self {
+        TurnState {
+            turn: 1,
+            phase: Faction::Player,
+        }
+    }
+}
+
+/// Hand over to the other side. Ignored unless the given faction's phase is in progress,
+/// so that a phase can't be ended twice.
+///
+/// Only sent by `CommandPlugin`, for `BattleCommand::EndPhase`.
+pub struct EndPhaseEvent(pub Faction);
+/// Sent when a faction's phase begins, along with the turn number
+pub struct PhaseStartedEvent(pub u32, pub Faction);
+
+pub(crate) struct TurnPlugin;
+
+impl Plugin for TurnPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TurnState::default())
+            .add_event::<EndPhaseEvent>()
+            .add_event::<PhaseStartedEvent>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::Deployment).with_system(Self::reset_turns),
+            )
+            .add_system_set(SystemSet::on_enter(AppState::Battle).with_system(Self::start_battle))
+            .add_system_set(
+                SystemSet::on_update(AppState::Battle)
+                    .with_system(Self::end_player_phase.before("apply_commands"))
+                    .with_system(Self::advance_phase.after("apply_commands")),
+            );
+    }
+}
+
+impl TurnPlugin {
+    fn reset_turns(mut turn_state: ResMut<TurnState>) {
+        *turn_state = TurnState::default();
+    }
+
+    fn start_battle(turn_state: Res<TurnState>, mut phase_events: EventWriter<PhaseStartedEvent>) {
+        phase_events.send(PhaseStartedEvent(turn_state.turn, turn_state.phase));
+    }
+
+    /// Ends the player's phase on `InputAction::EndPhase`, or once all of their units have
+    /// acted.
+    ///
+    /// Waits for any moves and attacks in progress to finish first.
+    fn end_player_phase(
+        actions: Res<Input<InputAction>>,
+        turn_state: Res<TurnState>,
+        unit_query: Query<(&Unit, &ActionQueue)>,
+        mut command_events: EventWriter<CommandEvent>,
+    ) {
+        if turn_state.phase != Faction::Player {
+            return;
+        }
+
+        let player_units = unit_query
+            .iter()
+            .filter(|(unit, _)| unit.faction == Faction::Player)
+            .collect::<Vec<_>>();
+
+        if player_units.iter().any(|(_, queue)| !queue.is_idle()) {
+            return;
+        }
+
+        if actions.just_pressed(InputAction::EndPhase)
+            || player_units.iter().all(|(unit, _)| unit.has_acted)
+        {
+            command_events.send(CommandEvent(BattleCommand::EndPhase(Faction::Player)));
+        }
+    }
+
+    /// Starts the next phase, committing every unit to where it stands and readying the
+    /// units whose phase it is
+    fn advance_phase(
+        mut commands: Commands,
+        mut events: EventReader<EndPhaseEvent>,
+        mut turn_state: ResMut<TurnState>,
+        selected_unit: Res<SelectedUnit>,
+        mut unit_query: Query<(Entity, &mut Unit)>,
+        mut deselect_events: EventWriter<DeselectUnitEvent>,
+        mut phase_events: EventWriter<PhaseStartedEvent>,
+    ) {
+        for EndPhaseEvent(faction) in events.iter() {
+            if *faction != turn_state.phase {
+                continue;
+            }
+
+            match turn_state.phase {
+                Faction::Player => turn_state.phase = Faction::Enemy,
+                Faction::Enemy => {
+                    turn_state.phase = Faction::Player;
+                    turn_state.turn += 1;
+                }
+            }
+
+            if let SelectedUnit::Some { entity, mode: _ } = *selected_unit {
+                deselect_events.send(DeselectUnitEvent(entity));
+            }
+
+            for (entity, mut unit) in unit_query.iter_mut() {
+                commands.entity(entity).remove::<PreviousMove>();
+
+                if unit.faction == turn_state.phase {
+                    unit.has_moved = false;
+                    unit.has_acted = false;
+                }
+            }
+
+            phase_events.send(PhaseStartedEvent(turn_state.turn, turn_state.phase));
+        }
+    }
+}
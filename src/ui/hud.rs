@@ -0,0 +1,164 @@
+use bevy::prelude::*;
+
+use crate::{
+    camera::CameraView,
+    controls::{Controls, InputAction},
+    objectives::Objectives,
+    replay::Replay,
+    state::AppState,
+    tile_map::{CurrentMap, MapDefinition},
+    turns::TurnState,
+    units::{Faction, SelectMode, SelectedUnit},
+};
+
+use super::UiFont;
+
+const HUD_FONT_SIZE: f32 = 12.0;
+
+/// Turn counter and objectives, shown in the top left corner during a battle
+#[derive(Component)]
+struct Hud;
+
+pub(crate) struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(AppState::Deployment).with_system(Self::spawn_hud))
+            // battles resumed from a save skip deployment
+            .add_system_set(SystemSet::on_enter(AppState::Battle).with_system(Self::spawn_hud))
+            .add_system_set(
+                SystemSet::on_update(AppState::Deployment).with_system(Self::update_hud),
+            )
+            .add_system_set(SystemSet::on_update(AppState::Battle).with_system(Self::update_hud))
+            .add_system_set(SystemSet::on_exit(AppState::Battle).with_system(Self::despawn_hud));
+    }
+}
+
+impl HudPlugin {
+    fn spawn_hud(mut commands: Commands, font: Res<UiFont>, hud_query: Query<(), With<Hud>>) {
+        if !hud_query.is_empty() {
+            return;
+        }
+
+        commands
+            .spawn_bundle(TextBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        left: Val::Px(8.0),
+                        top: Val::Px(8.0),
+                        ..default()
+                    },
+                    ..default()
+                },
+                text: Text::with_section(
+                    "",
+                    TextStyle {
+                        font: font.0.clone(),
+                        font_size: HUD_FONT_SIZE,
+                        color: Color::WHITE,
+                    },
+                    default(),
+                ),
+                ..default()
+            })
+            .insert(Hud);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn update_hud(
+        state: Res<State<AppState>>,
+        turn_state: Res<TurnState>,
+        objectives: Res<Objectives>,
+        replay: Res<Replay>,
+        camera_view: Res<CameraView>,
+        selected_unit: Res<SelectedUnit>,
+        controls: Res<Controls>,
+        current_map: Res<CurrentMap>,
+        definitions: Res<Assets<MapDefinition>>,
+        mut hud_query: Query<&mut Text, With<Hud>>,
+    ) {
+        let map_name = definitions
+            .get(&current_map.0)
+            .map_or("", |definition| definition.name.as_str());
+
+        let mut lines = vec![match (state.current(), turn_state.phase) {
+            (AppState::Battle, Faction::Player) => {
+                format!("{}: turn {}, your phase", map_name, turn_state.turn)
+            }
+            (AppState::Battle, Faction::Enemy) => {
+                format!("{}: turn {}, enemy phase", map_name, turn_state.turn)
+            }
+            _ => format!("{}: deployment", map_name),
+        }];
+
+        if let Replay::Playing(playback) = &*replay {
+            let (played, total) = playback.progress();
+            lines.push(format!(
+                "Replay {}/{}{} ({} pause, {} step, {}/{} speed)",
+                played,
+                total,
+                if playback.is_paused() { ", paused" } else { "" },
+                controls.prompt(InputAction::ReplayPause),
+                controls.prompt(InputAction::ReplayStep),
+                controls.prompt(InputAction::ReplayFaster),
+                controls.prompt(InputAction::ReplaySlower),
+            ));
+        }
+
+        if let SelectedUnit::Some { entity: _, mode } = *selected_unit {
+            let choice = match mode {
+                SelectMode::Move => None,
+                SelectMode::ActionMenu => Some(format!(
+                    "Actions: attack ({})",
+                    controls.prompt(InputAction::Confirm)
+                )),
+                SelectMode::AttackTarget => Some(format!(
+                    "Choose a unit to attack ({})",
+                    controls.prompt(InputAction::Confirm)
+                )),
+            };
+
+            if let Some(choice) = choice {
+                lines.push(format!(
+                    "{}, back ({})",
+                    choice,
+                    controls.prompt(InputAction::Cancel)
+                ));
+            }
+        }
+
+        if !camera_view.auto_follow {
+            lines.push(format!(
+                "Camera follow off ({})",
+                controls.prompt(InputAction::ToggleCameraFollow)
+            ));
+        }
+
+        lines.push("Victory:".to_string());
+        for objective in objectives.0.iter().filter(|o| !o.is_defeat_condition()) {
+            lines.push(format!("  {}", objective.describe(&turn_state)));
+        }
+
+        lines.push("Defeat:".to_string());
+        lines.push("  All of your units are defeated".to_string());
+        for objective in objectives.0.iter().filter(|o| o.is_defeat_condition()) {
+            lines.push(format!("  {}", objective.describe(&turn_state)));
+        }
+
+        let value = lines.join("\n");
+
+        for mut text in hud_query.iter_mut() {
+            // avoid relaying out the text every frame
+            if text.sections[0].value != value {
+                text.sections[0].value = value.clone();
+            }
+        }
+    }
+
+    fn despawn_hud(mut commands: Commands, hud_query: Query<Entity, With<Hud>>) {
+        for entity in hud_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use bevy::prelude::*;
+
+use crate::{
+    controls::{Controls, InputAction},
+    replay, save,
+    state::{AppState, BattleOutcome},
+    tile_map::CurrentMap,
+    units::UnitClasses,
+};
+
+use super::UiFont;
+
+const TITLE_FONT_SIZE: f32 = 40.0;
+const PROMPT_FONT_SIZE: f32 = 16.0;
+
+/// Root node of the text shown for the current `AppState`, despawned when leaving it
+#[derive(Component)]
+struct Screen;
+
+/// The title screen's "press enter" line, which says that the game is loading until it's
+/// ready to start
+#[derive(Component)]
+struct StartPrompt;
+
+pub(crate) struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_enter(AppState::TitleMenu).with_system(Self::spawn_title_screen),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::TitleMenu)
+                .with_system(Self::update_start_prompt)
+                .with_system(Self::start_game),
+        )
+        .add_system_set(SystemSet::on_exit(AppState::TitleMenu).with_system(despawn_screen))
+        .add_system_set(
+            SystemSet::on_enter(AppState::Deployment)
+                .with_system(Self::spawn_deployment_instructions),
+        )
+        .add_system_set(SystemSet::on_exit(AppState::Deployment).with_system(despawn_screen))
+        .add_system_set(
+            SystemSet::on_enter(AppState::Results).with_system(Self::spawn_results_screen),
+        )
+        .add_system_set(SystemSet::on_update(AppState::Results).with_system(Self::return_to_title))
+        .add_system_set(SystemSet::on_exit(AppState::Results).with_system(despawn_screen));
+    }
+}
+
+impl MenuPlugin {
+    fn spawn_title_screen(mut commands: Commands, font: Res<UiFont>, controls: Res<Controls>) {
+        let prompt = commands
+            .spawn_bundle(text_bundle("Loading...", PROMPT_FONT_SIZE, &font))
+            .insert(StartPrompt)
+            .id();
+
+        spawn_screen(&mut commands, JustifyContent::Center)
+            .with_children(|parent| {
+                parent.spawn_bundle(text_bundle("Zamlock", TITLE_FONT_SIZE, &font));
+            })
+            .add_child(prompt)
+            .with_children(|parent| {
+                if save::save_exists() {
+                    parent.spawn_bundle(text_bundle(
+                        &format!(
+                            "Press {} to resume your saved battle",
+                            controls.prompt(InputAction::LoadSave)
+                        ),
+                        PROMPT_FONT_SIZE,
+                        &font,
+                    ));
+                }
+
+                if replay::replay_exists() {
+                    parent.spawn_bundle(text_bundle(
+                        &format!(
+                            "Press {} to watch your last battle",
+                            controls.prompt(InputAction::PlayReplay)
+                        ),
+                        PROMPT_FONT_SIZE,
+                        &font,
+                    ));
+                }
+            });
+    }
+
+    fn update_start_prompt(
+        asset_server: Res<AssetServer>,
+        unit_classes: Res<UnitClasses>,
+        current_map: Res<CurrentMap>,
+        controls: Res<Controls>,
+        mut prompt_query: Query<&mut Text, With<StartPrompt>>,
+    ) {
+        if !unit_classes.is_loaded(&asset_server) || !current_map.is_loaded(&asset_server) {
+            return;
+        }
+
+        let value = format!("Press {} to start", controls.prompt(InputAction::Start));
+
+        for mut text in prompt_query.iter_mut() {
+            let section = &mut text.sections[0];
+            if section.value != value {
+                section.value = value.clone();
+            }
+        }
+    }
+
+    fn start_game(
+        mut state: ResMut<State<AppState>>,
+        mut actions: ResMut<Input<InputAction>>,
+        asset_server: Res<AssetServer>,
+        unit_classes: Res<UnitClasses>,
+        current_map: Res<CurrentMap>,
+    ) {
+        if !unit_classes.is_loaded(&asset_server) || !current_map.is_loaded(&asset_server) {
+            return;
+        }
+
+        // clear the key press so the next state doesn't see it too
+        if actions.clear_just_pressed(InputAction::Start) {
+            state
+                .set(AppState::Deployment)
+                .expect("Couldn't start deployment");
+        }
+    }
+
+    fn spawn_deployment_instructions(
+        mut commands: Commands,
+        font: Res<UiFont>,
+        controls: Res<Controls>,
+    ) {
+        spawn_screen(&mut commands, JustifyContent::FlexEnd).with_children(|parent| {
+            parent.spawn_bundle(text_bundle(
+                "Click or select the marked tiles to deploy your units",
+                PROMPT_FONT_SIZE,
+                &font,
+            ));
+            parent.spawn_bundle(text_bundle(
+                &format!(
+                    "Press {} to begin the battle",
+                    controls.prompt(InputAction::Start)
+                ),
+                PROMPT_FONT_SIZE,
+                &font,
+            ));
+        });
+    }
+
+    fn spawn_results_screen(
+        mut commands: Commands,
+        font: Res<UiFont>,
+        outcome: Res<BattleOutcome>,
+        controls: Res<Controls>,
+    ) {
+        let title = match *outcome {
+            BattleOutcome::Victory => "Victory!",
+            BattleOutcome::Defeat => "Defeat",
+            BattleOutcome::Undecided => "Battle over",
+        };
+
+        spawn_screen(&mut commands, JustifyContent::Center).with_children(|parent| {
+            parent.spawn_bundle(text_bundle(title, TITLE_FONT_SIZE, &font));
+            parent.spawn_bundle(text_bundle(
+                &format!(
+                    "Press {} to return to the title screen",
+                    controls.prompt(InputAction::Start)
+                ),
+                PROMPT_FONT_SIZE,
+                &font,
+            ));
+        });
+    }
+
+    fn return_to_title(
+        mut state: ResMut<State<AppState>>,
+        mut actions: ResMut<Input<InputAction>>,
+    ) {
+        if actions.clear_just_pressed(InputAction::Start) {
+            state
+                .set(AppState::TitleMenu)
+                .expect("Couldn't return to title menu");
+        }
+    }
+}
+
+/// Spawns a full screen column for lines of text, justified vertically by `justify_content`
+fn spawn_screen<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    justify_content: JustifyContent,
+) -> bevy::ecs::system::EntityCommands<'w, 's, 'a> {
+    let mut screen = commands.spawn_bundle(NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+            // ui y runs bottom to top, so reverse the column to lay text out top to bottom
+            flex_direction: FlexDirection::ColumnReverse,
+            justify_content,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        color: Color::NONE.into(),
+        ..default()
+    });
+    screen.insert(Screen);
+    screen
+}
+
+fn text_bundle(value: &str, font_size: f32, font: &UiFont) -> TextBundle {
+    TextBundle {
+        style: Style {
+            margin: Rect::all(Val::Px(4.0)),
+            ..default()
+        },
+        text: Text::with_section(
+            value,
+            TextStyle {
+                font: font.0.clone(),
+                font_size,
+                color: Color::WHITE,
+            },
+            default(),
+        ),
+        ..default()
+    }
+}
+
+fn despawn_screen(mut commands: Commands, screen_query: Query<Entity, With<Screen>>) {
+    for entity in screen_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
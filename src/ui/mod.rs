@@ -0,0 +1,44 @@
+use bevy::prelude::*;
+
+mod hud;
+mod menus;
+mod panel;
+mod tile_info;
+mod tooltip;
+
+use hud::HudPlugin;
+use menus::MenuPlugin;
+use tile_info::TileInfoPlugin;
+use tooltip::UnitTooltipPlugin;
+
+/// Font used for all on screen text
+#[derive(Default)]
+pub struct UiFont(pub Handle<Font>);
+
+struct UiFontPlugin;
+
+impl Plugin for UiFontPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(UiFont::default())
+            .add_startup_system(Self::load_font);
+    }
+}
+
+impl UiFontPlugin {
+    fn load_font(assets: Res<AssetServer>, mut font: ResMut<UiFont>) {
+        font.0 = assets.load("fonts/DejaVuSans.ttf");
+    }
+}
+
+pub struct UiPluginGroup;
+
+impl PluginGroup for UiPluginGroup {
+    fn build(&mut self, group: &mut bevy::app::PluginGroupBuilder) {
+        group
+            .add(UiFontPlugin)
+            .add(MenuPlugin)
+            .add(HudPlugin)
+            .add(UnitTooltipPlugin)
+            .add(TileInfoPlugin);
+    }
+}
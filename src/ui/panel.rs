@@ -0,0 +1,116 @@
+use std::marker::PhantomData;
+
+use bevy::prelude::*;
+
+use crate::state::AppState;
+
+use super::UiFont;
+
+const PANEL_FONT_SIZE: f32 = 12.0;
+
+/// Marks a text panel that's shown through deployment and battle. The systems that fill a
+/// panel in only set its `PanelContent`, labelled `"fill_panels"`, and `PanelPlugin` does
+/// the rest.
+pub(crate) trait Panel: Component + Default {
+    /// Where the panel is drawn, unless its `PanelContent` moves it
+    fn position() -> Rect<Val>;
+}
+
+/// What a panel shows, and where
+#[derive(Component, Default)]
+pub(crate) struct PanelContent {
+    /// the panel is hidden while this is `None`
+    pub(crate) text: Option<String>,
+    /// for panels that follow something around the screen
+    pub(crate) position: Option<Rect<Val>>,
+}
+
+/// Spawns the `T` panel for each battle, keeps it in step with its `PanelContent`, and
+/// despawns it once the battle is over
+pub(crate) struct PanelPlugin<T>(PhantomData<fn() -> T>);
+
+impl<T> Default for PanelPlugin<T> {
+    fn default() -> Self {
+        PanelPlugin(PhantomData)
+    }
+}
+
+impl<T: Panel> Plugin for PanelPlugin<T> {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_enter(AppState::Deployment).with_system(Self::spawn_panel),
+        )
+        // battles resumed from a save skip deployment
+        .add_system_set(SystemSet::on_enter(AppState::Battle).with_system(Self::spawn_panel))
+        .add_system(Self::show_panel.after("fill_panels"))
+        .add_system_set(SystemSet::on_exit(AppState::Battle).with_system(Self::despawn_panel));
+    }
+}
+
+impl<T: Panel> PanelPlugin<T> {
+    fn spawn_panel(mut commands: Commands, font: Res<UiFont>, panel_query: Query<(), With<T>>) {
+        if !panel_query.is_empty() {
+            return;
+        }
+
+        commands
+            .spawn_bundle(TextBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: T::position(),
+                    ..default()
+                },
+                text: Text::with_section(
+                    "",
+                    TextStyle {
+                        font: font.0.clone(),
+                        font_size: PANEL_FONT_SIZE,
+                        color: Color::WHITE,
+                    },
+                    default(),
+                ),
+                visibility: Visibility { is_visible: false },
+                ..default()
+            })
+            .insert(PanelContent::default())
+            .insert(T::default());
+    }
+
+    /// Shows the panel's `PanelContent`, hiding it while there's no text
+    fn show_panel(
+        mut panel_query: Query<(&PanelContent, &mut Text, &mut Style, &mut Visibility), With<T>>,
+    ) {
+        for (content, mut text, mut style, mut visibility) in panel_query.iter_mut() {
+            let value = match &content.text {
+                Some(value) => value,
+                None => {
+                    if visibility.is_visible {
+                        visibility.is_visible = false;
+                    }
+                    continue;
+                }
+            };
+
+            // avoid relaying out the text every frame
+            if text.sections[0].value != *value {
+                text.sections[0].value = value.clone();
+            }
+
+            if let Some(position) = content.position {
+                if style.position != position {
+                    style.position = position;
+                }
+            }
+
+            if !visibility.is_visible {
+                visibility.is_visible = true;
+            }
+        }
+    }
+
+    fn despawn_panel(mut commands: Commands, panel_query: Query<Entity, With<T>>) {
+        for entity in panel_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
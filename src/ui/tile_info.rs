@@ -0,0 +1,112 @@
+use bevy::prelude::*;
+
+use crate::{
+    objectives::Objectives,
+    state::AppState,
+    tile_map::{ActiveTile, Map, Tile, TileHeight},
+    units::{DangerZone, ValidMove},
+};
+
+use super::panel::{Panel, PanelContent, PanelPlugin};
+
+/// Everything there is to know about the `ActiveTile`, shown in the bottom left corner
+#[derive(Component, Default)]
+struct TileInfo;
+
+impl Panel for TileInfo {
+    fn position() -> Rect<Val> {
+        Rect {
+            left: Val::Px(8.0),
+            bottom: Val::Px(8.0),
+            ..default()
+        }
+    }
+}
+
+pub(crate) struct TileInfoPlugin;
+
+impl Plugin for TileInfoPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(PanelPlugin::<TileInfo>::default())
+            .add_system(Self::fill_tile_info.label("fill_panels"));
+    }
+}
+
+impl TileInfoPlugin {
+    /// Describes the `ActiveTile`, if there is one
+    fn fill_tile_info(
+        state: Res<State<AppState>>,
+        active_tile: Res<ActiveTile>,
+        objectives: Res<Objectives>,
+        danger_zone: Res<DangerZone>,
+        map_query: Query<&Map>,
+        tile_query: Query<(&Tile, Option<&ValidMove>)>,
+        mut tile_info_query: Query<&mut PanelContent, With<TileInfo>>,
+    ) {
+        let mut content = match tile_info_query.get_single_mut() {
+            Ok(content) => content,
+            Err(_) => return,
+        };
+
+        let (tile, valid_move) = match active_tile.0.and_then(|entity| tile_query.get(entity).ok())
+        {
+            Some(tile) => tile,
+            None => {
+                content.text = None;
+                return;
+            }
+        };
+
+        let mut lines = vec![
+            format!("Tile ({}, {})", tile.pos.x, tile.pos.z),
+            format!("Elevation {}", tile.pos.y),
+            match tile.height {
+                TileHeight::Full => "Full height".to_string(),
+                TileHeight::Half => "Half height".to_string(),
+            },
+            format!("{:?}, move cost {}", tile.terrain, tile.terrain.move_cost()),
+        ];
+
+        if valid_move.is_some() {
+            lines.push("In move range".to_string());
+        }
+
+        let mut effects = vec![];
+
+        let defence_bonus = tile.terrain.defence_bonus();
+        if defence_bonus > 0 {
+            effects.push(format!("Defence +{}", defence_bonus));
+        }
+
+        if *state.current() == AppState::Deployment
+            && map_query
+                .iter()
+                .any(|map| map.deployment_tiles.contains(&tile.pos))
+        {
+            effects.push("Deployment tile".to_string());
+        }
+
+        for objective in objectives.0.iter() {
+            if objective.tiles().contains(&tile.pos) {
+                effects.push(
+                    if objective.is_defeat_condition() {
+                        "Defended tile"
+                    } else {
+                        "Escape tile"
+                    }
+                    .to_string(),
+                );
+            }
+        }
+
+        if danger_zone.is_threatened(tile.pos) {
+            effects.push("Threatened by enemies".to_string());
+        }
+
+        if !effects.is_empty() {
+            lines.push(effects.join(", "));
+        }
+
+        content.text = Some(lines.join("\n"));
+    }
+}
@@ -0,0 +1,87 @@
+use bevy::prelude::*;
+
+use crate::{
+    camera::MainCamera,
+    tile_map::HoveredUnit,
+    units::{Faction, Unit, UnitName},
+};
+
+use super::panel::{Panel, PanelContent, PanelPlugin};
+
+/// How far up and to the right of the hovered unit's feet the tooltip is drawn, in logical
+/// pixels
+const TOOLTIP_OFFSET: (f32, f32) = (12.0, 24.0);
+
+/// Name, hp and faction of the `HoveredUnit`, drawn next to it
+#[derive(Component, Default)]
+struct UnitTooltip;
+
+impl Panel for UnitTooltip {
+    // always moved next to the hovered unit before it's shown
+    fn position() -> Rect<Val> {
+        default()
+    }
+}
+
+pub(crate) struct UnitTooltipPlugin;
+
+impl Plugin for UnitTooltipPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(PanelPlugin::<UnitTooltip>::default())
+            .add_system(Self::fill_tooltip.label("fill_panels"));
+    }
+}
+
+impl UnitTooltipPlugin {
+    /// Fills in the tooltip for the `HoveredUnit` and keeps it beside the unit as it and
+    /// the camera move
+    fn fill_tooltip(
+        hovered_unit: Res<HoveredUnit>,
+        windows: Res<Windows>,
+        images: Res<Assets<Image>>,
+        camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+        unit_query: Query<(&Unit, Option<&UnitName>, &GlobalTransform)>,
+        mut tooltip_query: Query<&mut PanelContent, With<UnitTooltip>>,
+    ) {
+        let mut content = match tooltip_query.get_single_mut() {
+            Ok(content) => content,
+            Err(_) => return,
+        };
+
+        let shown = hovered_unit
+            .0
+            .and_then(|entity| unit_query.get(entity).ok())
+            .and_then(|(unit, name, transform)| {
+                let (camera, camera_transform) = camera_query.get_single().ok()?;
+                let screen_pos = camera.world_to_screen(
+                    &windows,
+                    &images,
+                    camera_transform,
+                    transform.translation,
+                )?;
+
+                Some((unit, name, screen_pos))
+            });
+
+        *content = match shown {
+            Some((unit, name, screen_pos)) => PanelContent {
+                text: Some(format!(
+                    "{}\nHP {}/{}\n{}",
+                    name.map_or(unit.class.as_str(), |UnitName(name)| name.as_str()),
+                    unit.hp,
+                    unit.max_hp,
+                    match unit.faction {
+                        Faction::Player => "Your unit",
+                        Faction::Enemy => "Enemy",
+                    }
+                )),
+                position: Some(Rect {
+                    left: Val::Px(screen_pos.x + TOOLTIP_OFFSET.0),
+                    bottom: Val::Px(screen_pos.y + TOOLTIP_OFFSET.1),
+                    ..default()
+                }),
+            },
+            None => PanelContent::default(),
+        };
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::{HashSet, VecDeque};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    command::{BattleCommand, CommandEvent},
+    game_speed::GameSpeed,
+    state::AppState,
+    tile_map::{Map, Pos, SetPathEvent, TargetUnitEvent},
+};
+
+use super::{
+    class::Ability,
+    combat::AttackEvent,
+    movement::{ChangeFacingEvent, MovementFinished, Waypoints},
+    unit::Unit,
+    Direction,
+};
+
+/// How long an attack holds up a unit's `ActionQueue`, in (game speed adjusted) seconds
+const ATTACK_DURATION: f32 = 0.4;
+
+/// Something a unit can be told to do as part of a scripted sequence
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Action {
+    /// walk along a route, as in `SetPathEvent`
+    Move(Vec<Pos>),
+    /// attack the unit standing at this `Pos`
+    Attack(Pos),
+    Face(Direction),
+    /// do nothing for this many seconds
+    Wait(f32),
+}
+
+/// What a unit is waiting on before it can start its next `Action`
+enum InProgress {
+    Moving,
+    Waiting(Timer),
+}
+
+/// Actions for a unit to carry out one after the other, e.g. move then attack
+#[derive(Component, Default)]
+pub struct ActionQueue {
+    actions: VecDeque<Action>,
+    in_progress: Option<InProgress>,
+}
+
+impl ActionQueue {
+    pub fn is_idle(&self) -> bool {
+        self.actions.is_empty() && self.in_progress.is_none()
+    }
+}
+
+/// Add actions to the end of a unit's `ActionQueue`
+pub struct QueueActionsEvent(pub Entity, pub Vec<Action>);
+
+pub struct ActionPlugin;
+
+impl Plugin for ActionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<QueueActionsEvent>().add_system_set(
+            SystemSet::on_update(AppState::Battle)
+                .with_system(
+                    Self::plan_attacks
+                        .label("plan_attacks")
+                        .after("click_tile")
+                        .before("apply_commands"),
+                )
+                .with_system(Self::queue_actions.after("apply_commands"))
+                .with_system(Self::run_actions.after(Self::queue_actions)),
+        );
+    }
+}
+
+impl ActionPlugin {
+    /// Turns a targeted unit into an attack, moving into range first if need be.
+    ///
+    /// Runs before the deselection that goes with targeting clears the `Waypoints`.
+    fn plan_attacks(
+        mut events: EventReader<TargetUnitEvent>,
+        mut command_events: EventWriter<CommandEvent>,
+        waypoints: Res<Waypoints>,
+        unit_query: Query<(Entity, &Unit)>,
+        map_query: Query<&Map>,
+    ) {
+        for TargetUnitEvent(attacker_entity, target_entity) in events.iter() {
+            let map = map_query.get_single().expect("Not exactly one map");
+
+            let (attacker, target) = match (
+                unit_query.get(*attacker_entity),
+                unit_query.get(*target_entity),
+            ) {
+                (Ok((_, attacker)), Ok((_, target))) => (attacker, target),
+                _ => continue,
+            };
+
+            if attacker.faction == target.faction
+                || !attacker.can(Ability::Attack)
+                || attacker.has_acted
+            {
+                continue;
+            }
+
+            let occupied = unit_query
+                .iter()
+                .filter(|(entity, _)| entity != attacker_entity)
+                .map(|(_, unit)| unit.pos)
+                .collect::<HashSet<Pos>>();
+
+            let route = match attacker.get_attack_route(target.pos, &waypoints.0, map, &occupied) {
+                Some(route) => route,
+                None => continue,
+            };
+
+            // a unit that has already moved this phase has to attack from where it is
+            if attacker.has_moved && !route.is_empty() {
+                continue;
+            }
+
+            let mut actions = vec![];
+            if !route.is_empty() {
+                actions.push(Action::Move(route));
+            }
+            actions.push(Action::Attack(target.pos));
+
+            command_events.send(CommandEvent(BattleCommand::Act(attacker.pos, actions)));
+        }
+    }
+
+    fn queue_actions(
+        mut events: EventReader<QueueActionsEvent>,
+        mut queue_query: Query<&mut ActionQueue>,
+    ) {
+        for QueueActionsEvent(entity, actions) in events.iter() {
+            if let Ok(mut queue) = queue_query.get_mut(*entity) {
+                queue.actions.extend(actions.iter().cloned());
+            }
+        }
+    }
+
+    /// Starts each unit's next action once it has finished the previous one
+    #[allow(clippy::too_many_arguments)]
+    fn run_actions(
+        time: Res<Time>,
+        game_speed: Res<GameSpeed>,
+        mut finished_events: EventReader<MovementFinished>,
+        mut queue_query: Query<(Entity, &mut ActionQueue)>,
+        unit_query: Query<(Entity, &Unit)>,
+        mut path_events: EventWriter<SetPathEvent>,
+        mut attack_events: EventWriter<AttackEvent>,
+        mut facing_events: EventWriter<ChangeFacingEvent>,
+    ) {
+        let finished_moving = finished_events
+            .iter()
+            .map(|MovementFinished(entity)| *entity)
+            .collect::<HashSet<Entity>>();
+        let delta = time.delta().mul_f32(game_speed.multiplier());
+
+        for (entity, mut queue) in queue_query.iter_mut() {
+            if queue.is_idle() {
+                continue;
+            }
+
+            let still_busy = match queue.in_progress.as_mut() {
+                Some(InProgress::Moving) => !finished_moving.contains(&entity),
+                Some(InProgress::Waiting(timer)) => !timer.tick(delta).finished(),
+                None => false,
+            };
+
+            if still_busy {
+                continue;
+            }
+
+            queue.in_progress = match queue.actions.pop_front() {
+                Some(Action::Move(route)) => {
+                    path_events.send(SetPathEvent(entity, route));
+                    Some(InProgress::Moving)
+                }
+                Some(Action::Attack(target_pos)) => {
+                    // the target may have been defeated in the meantime
+                    if let Some((target, _)) =
+                        unit_query.iter().find(|(_, unit)| unit.pos == target_pos)
+                    {
+                        attack_events.send(AttackEvent(entity, target));
+                    }
+                    Some(InProgress::Waiting(Timer::from_seconds(
+                        ATTACK_DURATION,
+                        false,
+                    )))
+                }
+                Some(Action::Face(direction)) => {
+                    facing_events.send(ChangeFacingEvent(entity, direction));
+                    None
+                }
+                Some(Action::Wait(seconds)) => {
+                    Some(InProgress::Waiting(Timer::from_seconds(seconds, false)))
+                }
+                None => None,
+            };
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use ordered_float::OrderedFloat;
+
+use crate::{
+    command::{BattleCommand, CommandEvent},
+    state::AppState,
+    tile_map::{Map, Pos},
+    turns::{PhaseStartedEvent, TurnState},
+};
+
+use super::{
+    actions::{Action, ActionQueue},
+    class::Ability,
+    unit::{Faction, Unit},
+};
+
+/// The factions whose phases are played by the AI rather than the player
+pub struct AiControlled(pub Vec<Faction>);
+
+impl Default for AiControlled {
+    fn default() -> Self {
+        AiControlled(vec![Faction::Enemy])
+    }
+}
+
+/// Plays the phases of the `AiControlled` factions: each of their units in turn attacks the
+/// nearest opposing unit it can reach, or otherwise moves as close to one as it can
+pub struct AiPlugin;
+
+impl Plugin for AiPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AiControlled>().add_system_set(
+            SystemSet::on_update(AppState::Battle)
+                .with_system(Self::take_ai_turns.before("apply_commands")),
+        );
+    }
+}
+
+impl AiPlugin {
+    /// Waits for the acting unit's `ActionQueue` to empty before planning the next unit's
+    /// actions, and ends the phase once every unit has had a go
+    #[allow(clippy::too_many_arguments)]
+    fn take_ai_turns(
+        mut acting: Local<Option<Entity>>,
+        mut done: Local<HashSet<Entity>>,
+        mut phase_events: EventReader<PhaseStartedEvent>,
+        turn_state: Res<TurnState>,
+        ai_controlled: Res<AiControlled>,
+        unit_query: Query<(Entity, &Unit, &ActionQueue)>,
+        map_query: Query<&Map>,
+        mut command_events: EventWriter<CommandEvent>,
+    ) {
+        if phase_events.iter().count() > 0 {
+            *acting = None;
+            done.clear();
+        }
+
+        let faction = turn_state.phase;
+        if !ai_controlled.0.contains(&faction) {
+            return;
+        }
+
+        if let Some(entity) = *acting {
+            // units that have been defeated mid-action are finished with too
+            if let Ok((_, _, queue)) = unit_query.get(entity) {
+                if !queue.is_idle() {
+                    return;
+                }
+            }
+            *acting = None;
+        }
+
+        let (entity, unit) = match unit_query
+            .iter()
+            .find(|(entity, unit, _)| unit.faction == faction && !done.contains(entity))
+        {
+            Some((entity, unit, _)) => (entity, unit),
+            None => {
+                command_events.send(CommandEvent(BattleCommand::EndPhase(faction)));
+                return;
+            }
+        };
+
+        done.insert(entity);
+
+        let map = map_query.get_single().expect("Not exactly one map");
+        let occupied = unit_query
+            .iter()
+            .filter(|(other, _, _)| *other != entity)
+            .map(|(_, other, _)| other.pos)
+            .collect::<HashSet<Pos>>();
+        let mut targets = unit_query
+            .iter()
+            .filter(|(_, other, _)| other.faction != faction)
+            .map(|(_, target, _)| target.pos)
+            .collect::<Vec<Pos>>();
+        targets.sort_by_key(|pos| OrderedFloat(unit.pos.ground_distance(pos)));
+
+        let actions = plan_actions(unit, &targets, map, &occupied);
+        if !actions.is_empty() {
+            command_events.send(CommandEvent(BattleCommand::Act(unit.pos, actions)));
+            *acting = Some(entity);
+        }
+    }
+}
+
+/// Attack the nearest target in reach, or else head for the nearest target
+fn plan_actions(unit: &Unit, targets: &[Pos], map: &Map, occupied: &HashSet<Pos>) -> Vec<Action> {
+    if unit.can(Ability::Attack) {
+        for target_pos in targets {
+            if let Some(route) = unit.get_attack_route(*target_pos, &[], map, occupied) {
+                let mut actions = vec![];
+                if !route.is_empty() {
+                    actions.push(Action::Move(route));
+                }
+                actions.push(Action::Attack(*target_pos));
+
+                return actions;
+            }
+        }
+    }
+
+    let nearest_pos = match targets.first() {
+        Some(target) => target,
+        None => return vec![],
+    };
+
+    let current_distance = unit.pos.ground_distance(nearest_pos);
+    unit.get_valid_moves(map)
+        .into_iter()
+        .filter(|pos| !occupied.contains(pos))
+        .filter(|pos| pos.ground_distance(nearest_pos) < current_distance)
+        .min_by_key(|pos| OrderedFloat(pos.ground_distance(nearest_pos)))
+        .and_then(|pos| unit.get_route(&[], pos, map))
+        .map(|route| vec![Action::Move(route)])
+        .unwrap_or_default()
+}
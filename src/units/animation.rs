@@ -0,0 +1,301 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use bevy::{
+    asset::{AssetLoader, AssetPath, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    sprite::Anchor,
+};
+use serde::Deserialize;
+
+use crate::{game_speed::GameSpeed, tile_map::Map};
+
+use super::{combat::Defeated, unit::Unit, Direction};
+
+/// `move_speed`, in pixels per second, that walk animations are drawn for
+pub const WALK_REFERENCE_SPEED: f32 = 32.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum Animation {
+    Idle,
+    Walk,
+    Attack,
+    Hurt,
+    KO,
+}
+
+/// A sequence of frames from a `SpriteSheet`'s texture atlas
+#[derive(Clone, Debug, Deserialize)]
+pub struct AnimationClip {
+    /// indices into the sheet's `frames`
+    pub frames: Vec<usize>,
+    /// seconds each frame is shown for. For `Animation::Walk` this is the frame time at a
+    /// `move_speed` of `WALK_REFERENCE_SPEED`, and is scaled by the unit's actual speed
+    pub frame_time: f32,
+    /// clips that don't loop hold their last frame when they finish
+    #[serde(default = "default_looping")]
+    pub looping: bool,
+}
+
+fn default_looping() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+struct FrameRect {
+    min: (f32, f32),
+    max: (f32, f32),
+}
+
+/// The layout of a sprite sheet as it's written in a `.sheet.ron` file
+#[derive(Deserialize)]
+struct SpriteSheetDefinition {
+    /// path to the sheet's image, relative to the assets folder
+    image: String,
+    image_size: (f32, f32),
+    /// custom `Anchor` for the sheet's sprites, relative to their centre
+    anchor: (f32, f32),
+    frames: Vec<FrameRect>,
+    animations: HashMap<Animation, HashMap<Direction, AnimationClip>>,
+}
+
+/// Animations for a unit sprite sheet, loaded from a `.sheet.ron` file along with the
+/// texture atlas they index into
+#[derive(TypeUuid)]
+#[uuid = "5a1f3b4e-8c2d-4f6a-9b7e-0d3c2a1e4f5b"]
+pub struct SpriteSheet {
+    pub texture_atlas: Handle<TextureAtlas>,
+    /// custom `Anchor` for the sheet's sprites, relative to their centre, so that each
+    /// frame's feet land on the unit's tile
+    pub anchor: Vec2,
+    animations: HashMap<Animation, HashMap<Direction, AnimationClip>>,
+}
+
+impl SpriteSheet {
+    /// Returns the clip for `animation` in `direction`, falling back to `Animation::Idle`
+    /// when the sheet doesn't have frames for that animation
+    pub fn clip(&self, animation: Animation, direction: Direction) -> Option<&AnimationClip> {
+        self.animations
+            .get(&animation)
+            .and_then(|clips| clips.get(&direction))
+            .or_else(|| {
+                self.animations
+                    .get(&Animation::Idle)
+                    .and_then(|clips| clips.get(&direction))
+            })
+    }
+}
+
+#[derive(Default)]
+pub struct SpriteSheetLoader;
+
+impl AssetLoader for SpriteSheetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let definition: SpriteSheetDefinition = ron::de::from_bytes(bytes)?;
+
+            let image_path = AssetPath::new(PathBuf::from(&definition.image), None);
+            let image = load_context.get_handle(image_path.clone());
+
+            let mut atlas = TextureAtlas::new_empty(image, Vec2::from(definition.image_size));
+            for frame in definition.frames.iter() {
+                atlas.add_texture(bevy::sprite::Rect {
+                    min: Vec2::from(frame.min),
+                    max: Vec2::from(frame.max),
+                });
+            }
+
+            let texture_atlas = load_context.set_labeled_asset(
+                "texture_atlas",
+                LoadedAsset::new(atlas).with_dependency(image_path),
+            );
+
+            load_context.set_default_asset(LoadedAsset::new(SpriteSheet {
+                texture_atlas,
+                anchor: Vec2::from(definition.anchor),
+                animations: definition.animations,
+            }));
+
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["sheet.ron"]
+    }
+}
+
+/// Plays `SpriteSheet` animations on a unit's `TextureAtlasSprite`, swapping in the sheet's
+/// texture atlas once it has loaded
+#[derive(Component)]
+pub struct Animator {
+    sheet: Handle<SpriteSheet>,
+    animation: Animation,
+    direction: Direction,
+    /// multiplier for the current clip's frame time
+    speed: f32,
+    frame: usize,
+    elapsed: f32,
+}
+
+impl Animator {
+    pub fn new(sheet: Handle<SpriteSheet>, direction: Direction) -> Animator {
+        Animator {
+            sheet,
+            animation: Animation::Idle,
+            direction,
+            speed: 1.0,
+            frame: 0,
+            elapsed: 0.0,
+        }
+    }
+
+    /// Starts playing `animation` from its first frame, unless it's already playing
+    pub fn play(&mut self, animation: Animation) {
+        if self.animation != animation {
+            self.animation = animation;
+            self.frame = 0;
+            self.elapsed = 0.0;
+        }
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    pub fn face(&mut self, direction: Direction) {
+        self.direction = direction;
+    }
+}
+
+/// Play `Animation` on a unit, e.g. in response to combat
+pub struct PlayAnimationEvent(pub Entity, pub Animation);
+
+pub struct AnimationPlugin;
+
+impl Plugin for AnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<SpriteSheet>()
+            .init_asset_loader::<SpriteSheetLoader>()
+            .add_system(Self::add_unit_sprites)
+            .add_system(Self::play_animations)
+            .add_system(Self::relayout_units)
+            .add_system(Self::animate_sprites.after(Self::play_animations));
+    }
+}
+
+impl AnimationPlugin {
+    /// Gives newly spawned units a sprite to animate
+    fn add_unit_sprites(mut commands: Commands, unit_query: Query<Entity, Added<Animator>>) {
+        for entity in unit_query.iter() {
+            commands
+                .entity(entity)
+                .insert(TextureAtlasSprite::default())
+                .insert(Handle::<TextureAtlas>::default())
+                .insert(Visibility::default())
+                .insert(ComputedVisibility::default());
+        }
+    }
+
+    /// Moves every unit's sprite to wherever its tile is now drawn, after the view rotates
+    fn relayout_units(
+        map_query: Query<&Map, Changed<Map>>,
+        mut unit_query: Query<(&Unit, &mut Transform)>,
+        mut defeated_query: Query<(&Defeated, &mut Transform), Without<Unit>>,
+    ) {
+        let map = match map_query.get_single() {
+            Ok(map) => map,
+            Err(_) => return,
+        };
+
+        for (unit, mut transform) in unit_query.iter_mut() {
+            transform.translation = map.world_pos_to_unit_screen_pos_absolute(unit.pos);
+        }
+
+        for (Defeated(pos), mut transform) in defeated_query.iter_mut() {
+            transform.translation = map.world_pos_to_unit_screen_pos_absolute(*pos);
+        }
+    }
+
+    fn play_animations(
+        mut events: EventReader<PlayAnimationEvent>,
+        mut animator_query: Query<&mut Animator>,
+    ) {
+        for PlayAnimationEvent(entity, animation) in events.iter() {
+            if let Ok(mut animator) = animator_query.get_mut(*entity) {
+                animator.set_speed(1.0);
+                animator.play(*animation);
+            }
+        }
+    }
+
+    /// Advances each `Animator` and shows its current frame, facing the way the unit faces
+    /// given how the view is rotated
+    fn animate_sprites(
+        time: Res<Time>,
+        game_speed: Res<GameSpeed>,
+        sheets: Res<Assets<SpriteSheet>>,
+        map_query: Query<&Map>,
+        mut animator_query: Query<(
+            &mut Animator,
+            &mut TextureAtlasSprite,
+            &mut Handle<TextureAtlas>,
+        )>,
+    ) {
+        let rotation = map_query
+            .get_single()
+            .map(|map| map.rotation)
+            .unwrap_or_default();
+
+        for (mut animator, mut sprite, mut texture_atlas) in animator_query.iter_mut() {
+            let sheet = match sheets.get(&animator.sheet) {
+                Some(sheet) => sheet,
+                // still loading
+                None => continue,
+            };
+
+            if *texture_atlas != sheet.texture_atlas {
+                *texture_atlas = sheet.texture_atlas.clone();
+                sprite.anchor = Anchor::Custom(sheet.anchor);
+            }
+
+            let direction = rotation.screen_direction(animator.direction);
+            let clip = match sheet.clip(animator.animation, direction) {
+                Some(clip) => clip,
+                None => continue,
+            };
+
+            animator.elapsed += game_speed.delta_seconds(&time) * animator.speed;
+
+            while clip.frame_time > 0.0 && animator.elapsed >= clip.frame_time {
+                animator.elapsed -= clip.frame_time;
+
+                if animator.frame + 1 < clip.frames.len() {
+                    animator.frame += 1;
+                } else if clip.looping {
+                    animator.frame = 0;
+                } else {
+                    animator.elapsed = 0.0;
+
+                    // one-off reactions go back to standing around once they're done,
+                    // whereas being knocked out is permanent
+                    if matches!(animator.animation, Animation::Attack | Animation::Hurt) {
+                        animator.play(Animation::Idle);
+                    }
+                    break;
+                }
+            }
+
+            let frame = animator.frame.min(clip.frames.len().saturating_sub(1));
+            if let Some(index) = clip.frames.get(frame) {
+                if sprite.index != *index {
+                    sprite.index = *index;
+                }
+            }
+        }
+    }
+}
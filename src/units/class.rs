@@ -0,0 +1,139 @@
+use std::path::PathBuf;
+
+use bevy::{
+    asset::{AssetLoader, AssetPath, BoxedFuture, LoadContext, LoadState, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+};
+use serde::{Deserialize, Serialize};
+
+use super::animation::SpriteSheet;
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct Stats {
+    pub max_hp: u32,
+    /// damage dealt by each attack
+    pub attack: u32,
+    pub move_distance: usize,
+    /// pixels per second
+    pub move_speed: f32,
+    /// how far away (in tiles, ignoring elevation) a unit can attack from
+    pub attack_range: usize,
+}
+
+/// How far up or down a unit can step in one move
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct MovementProfile {
+    pub max_climb: f32,
+    pub max_drop: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Ability {
+    Attack,
+}
+
+/// A unit class as it's written in a `.class.ron` file
+#[derive(Deserialize)]
+struct UnitClassDefinition {
+    name: String,
+    /// path to the class's `.sheet.ron`, relative to the assets folder
+    sheet: String,
+    stats: Stats,
+    movement: MovementProfile,
+    #[serde(default)]
+    abilities: Vec<Ability>,
+}
+
+/// Everything needed to spawn a unit of a given class, loaded from a `.class.ron` file
+#[derive(TypeUuid)]
+#[uuid = "c3e8a0d2-6b1f-4e57-a9d4-7f2b8c6e1a30"]
+pub struct UnitClass {
+    pub name: String,
+    pub sheet: Handle<SpriteSheet>,
+    pub stats: Stats,
+    pub movement: MovementProfile,
+    pub abilities: Vec<Ability>,
+}
+
+#[derive(Default)]
+pub struct UnitClassLoader;
+
+impl AssetLoader for UnitClassLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let definition: UnitClassDefinition = ron::de::from_bytes(bytes)?;
+
+            let sheet_path = AssetPath::new(PathBuf::from(&definition.sheet), None);
+            let sheet = load_context.get_handle(sheet_path.clone());
+
+            load_context.set_default_asset(
+                LoadedAsset::new(UnitClass {
+                    name: definition.name,
+                    sheet,
+                    stats: definition.stats,
+                    movement: definition.movement,
+                    abilities: definition.abilities,
+                })
+                .with_dependency(sheet_path),
+            );
+
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["class.ron"]
+    }
+}
+
+/// Handles for every class in `assets/units/classes`
+#[derive(Default)]
+pub struct UnitClasses {
+    handles: Vec<Handle<UnitClass>>,
+}
+
+impl UnitClasses {
+    pub fn is_loaded(&self, asset_server: &AssetServer) -> bool {
+        self.load_state(asset_server) == LoadState::Loaded
+    }
+
+    /// `LoadState::Failed` if any class couldn't be loaded
+    pub fn load_state(&self, asset_server: &AssetServer) -> LoadState {
+        asset_server.get_group_load_state(self.handles.iter().map(|handle| handle.id))
+    }
+
+    /// Returns the class called `name`, if it has loaded
+    pub fn get<'a>(&self, name: &str, classes: &'a Assets<UnitClass>) -> Option<&'a UnitClass> {
+        self.handles
+            .iter()
+            .filter_map(|handle| classes.get(handle))
+            .find(|class| class.name == name)
+    }
+}
+
+pub struct UnitClassPlugin;
+
+impl Plugin for UnitClassPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<UnitClass>()
+            .init_asset_loader::<UnitClassLoader>()
+            .insert_resource(UnitClasses::default())
+            .add_startup_system(Self::load_classes);
+    }
+}
+
+impl UnitClassPlugin {
+    fn load_classes(asset_server: Res<AssetServer>, mut unit_classes: ResMut<UnitClasses>) {
+        unit_classes.handles = asset_server
+            .load_folder("units/classes")
+            .expect("Couldn't load unit classes")
+            .into_iter()
+            .map(|handle| handle.typed::<UnitClass>())
+            .collect();
+    }
+}
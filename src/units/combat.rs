@@ -0,0 +1,130 @@
+use bevy::prelude::*;
+
+use crate::{
+    state::AppState,
+    tile_map::{Map, Pos},
+};
+
+use super::{
+    actions::ActionQueue,
+    animation::{Animation, PlayAnimationEvent},
+    movement::{get_facing, ChangeFacingEvent, PreviousMove},
+    unit::{SelectedUnit, Unit},
+};
+
+/// One unit attacks another. Ignored if the target is out of the attacker's range.
+///
+/// The target takes the attacker's `attack` less the defence bonus of the terrain it's
+/// standing on, but always at least 1 damage.
+pub struct AttackEvent(pub Entity, pub Entity);
+/// Sent when a unit (first) takes damage from another (second), along with how much
+pub struct UnitDamagedEvent(pub Entity, pub Entity, pub u32);
+/// Sent when a unit runs out of hp
+pub struct UnitDefeatedEvent(pub Entity);
+
+/// Marks a unit that has been knocked out.
+///
+/// Defeated units lose their `Unit` component so that they drop out of gameplay, but
+/// their sprite stays behind where they fell.
+#[derive(Component)]
+pub struct Defeated(pub Pos);
+
+pub struct CombatPlugin;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<AttackEvent>()
+            .add_event::<UnitDamagedEvent>()
+            .add_event::<UnitDefeatedEvent>()
+            .add_event::<PlayAnimationEvent>()
+            .add_system_set(
+                SystemSet::on_update(AppState::Battle).with_system(Self::resolve_attacks),
+            );
+    }
+}
+
+impl CombatPlugin {
+    #[allow(clippy::too_many_arguments)]
+    fn resolve_attacks(
+        mut commands: Commands,
+        mut events: EventReader<AttackEvent>,
+        mut selected_unit: ResMut<SelectedUnit>,
+        mut unit_query: Query<&mut Unit>,
+        map_query: Query<&Map>,
+        mut facing_events: EventWriter<ChangeFacingEvent>,
+        mut animation_events: EventWriter<PlayAnimationEvent>,
+        mut damaged_events: EventWriter<UnitDamagedEvent>,
+        mut defeated_events: EventWriter<UnitDefeatedEvent>,
+    ) {
+        for AttackEvent(attacker_entity, target_entity) in events.iter() {
+            let target_pos = match unit_query.get(*target_entity) {
+                Ok(target) => target.pos,
+                Err(_) => continue,
+            };
+
+            let (attacker_pos, damage) = match unit_query.get_mut(*attacker_entity) {
+                Ok(mut attacker) => {
+                    if attacker.has_acted {
+                        warn!(
+                            "Ignoring attack by unit {:?} that has already acted",
+                            attacker_entity
+                        );
+                        continue;
+                    }
+
+                    if attacker.pos.ground_distance(&target_pos) > attacker.attack_range as f32 {
+                        warn!("Ignoring attack on out of range unit {:?}", target_entity);
+                        continue;
+                    }
+
+                    // attacking ends a unit's turn
+                    attacker.has_acted = true;
+                    (attacker.pos, attacker.attack)
+                }
+                Err(_) => continue,
+            };
+
+            let mut target = unit_query
+                .get_mut(*target_entity)
+                .expect("No unit for target entity");
+
+            facing_events.send(ChangeFacingEvent(
+                *attacker_entity,
+                get_facing(attacker_pos, target.pos),
+            ));
+            animation_events.send(PlayAnimationEvent(*attacker_entity, Animation::Attack));
+
+            // attacking commits a unit to where it's standing
+            commands.entity(*attacker_entity).remove::<PreviousMove>();
+
+            let defence = map_query
+                .get_single()
+                .map_or(0, |map| map.terrain(target.pos).defence_bonus());
+            let damage = damage.saturating_sub(defence).max(1);
+
+            target.hp = target.hp.saturating_sub(damage);
+            damaged_events.send(UnitDamagedEvent(*target_entity, *attacker_entity, damage));
+
+            if target.hp > 0 {
+                animation_events.send(PlayAnimationEvent(*target_entity, Animation::Hurt));
+                continue;
+            }
+
+            animation_events.send(PlayAnimationEvent(*target_entity, Animation::KO));
+            defeated_events.send(UnitDefeatedEvent(*target_entity));
+
+            commands
+                .entity(*target_entity)
+                .remove::<Unit>()
+                .remove::<ActionQueue>()
+                .remove::<PreviousMove>()
+                .insert(Defeated(target.pos));
+
+            if let SelectedUnit::Some { entity, mode: _ } = *selected_unit {
+                if entity == *target_entity {
+                    *selected_unit = SelectedUnit::None;
+                }
+            }
+        }
+    }
+}
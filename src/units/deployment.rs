@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::{
+    command::{BattleCommand, CommandEvent},
+    controls::InputAction,
+    state::AppState,
+    tile_map::{
+        spawn_overlay, ActiveTile, CurrentMap, Map, MapDefinition, OverlayLayer, Pos, Tile,
+    },
+};
+
+use super::{
+    class::{UnitClass, UnitClasses},
+    unit::{Faction, Unit, UnitName},
+};
+
+#[derive(Default)]
+struct DeploymentGraphics {
+    marker: Handle<Image>,
+}
+
+/// Tints a tile that the player can deploy a unit on
+#[derive(Component)]
+struct DeploymentMarker;
+
+/// The player's units, both those still waiting to be placed and those already on the map
+#[derive(Default)]
+struct Deployment {
+    undeployed: Vec<String>,
+    deployed: HashMap<Pos, (Entity, String)>,
+    /// whether the units that start on the map have been spawned, which has to wait until
+    /// the map exists
+    ready: bool,
+}
+
+/// Place the next unit from the roster on this deployment tile, or take back the unit
+/// that's already on it
+pub struct DeployEvent(pub Pos);
+
+pub struct DeploymentPlugin;
+
+impl Plugin for DeploymentPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Deployment::default())
+            .add_event::<DeployEvent>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::Deployment).with_system(Self::reset_deployment),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Deployment)
+                    .with_system(Self::prepare_battlefield)
+                    .with_system(Self::click_deployment_tile.before("apply_commands"))
+                    .with_system(Self::begin_battle.before("apply_commands"))
+                    .with_system(
+                        Self::deploy_units
+                            .after(Self::prepare_battlefield)
+                            .after("apply_commands"),
+                    ),
+            );
+    }
+}
+
+impl DeploymentPlugin {
+    fn reset_deployment(
+        mut deployment: ResMut<Deployment>,
+        current_map: Res<CurrentMap>,
+        definitions: Res<Assets<MapDefinition>>,
+    ) {
+        let definition = definitions
+            .get(&current_map.0)
+            .expect("Map definition not loaded");
+
+        *deployment = Deployment {
+            undeployed: definition.roster.clone(),
+            ..default()
+        };
+    }
+
+    /// Spawns the units that start on the map, once the map has been spawned
+    fn prepare_battlefield(
+        mut commands: Commands,
+        mut deployment: ResMut<Deployment>,
+        current_map: Res<CurrentMap>,
+        definitions: Res<Assets<MapDefinition>>,
+        unit_classes: Res<UnitClasses>,
+        classes: Res<Assets<UnitClass>>,
+        map_query: Query<&Map>,
+    ) {
+        if deployment.ready {
+            return;
+        }
+
+        let map = match map_query.get_single() {
+            Ok(map) => map,
+            Err(_) => return,
+        };
+
+        let definition = definitions
+            .get(&current_map.0)
+            .expect("Map definition not loaded");
+
+        for placement in definition.units.iter() {
+            let class = unit_classes
+                .get(&placement.class, &classes)
+                .expect("No such unit class");
+
+            let entity = Unit::spawn(&mut commands, map, class, placement.pos, placement.faction);
+            if let Some(name) = &placement.name {
+                commands.entity(entity).insert(UnitName(name.clone()));
+            }
+        }
+
+        deployment.ready = true;
+    }
+
+    fn click_deployment_tile(
+        actions: Res<Input<InputAction>>,
+        active_tile: Res<ActiveTile>,
+        tile_query: Query<&Tile>,
+        map_query: Query<&Map>,
+        mut command_events: EventWriter<CommandEvent>,
+    ) {
+        if !actions.just_pressed(InputAction::Confirm) {
+            return;
+        }
+
+        let tile = match active_tile.0.and_then(|entity| tile_query.get(entity).ok()) {
+            Some(tile) => tile,
+            None => return,
+        };
+
+        if let Ok(map) = map_query.get_single() {
+            if map.deployment_tiles.contains(&tile.pos) {
+                command_events.send(CommandEvent(BattleCommand::Deploy(tile.pos)));
+            }
+        }
+    }
+
+    /// Places the next unit from the roster on an empty deployment tile, or takes a deployed
+    /// unit back off the map
+    fn deploy_units(
+        mut commands: Commands,
+        mut deployment: ResMut<Deployment>,
+        mut events: EventReader<DeployEvent>,
+        unit_classes: Res<UnitClasses>,
+        classes: Res<Assets<UnitClass>>,
+        map_query: Query<&Map>,
+    ) {
+        if !deployment.ready {
+            return;
+        }
+
+        let map = map_query.get_single().expect("Not exactly one map");
+
+        for DeployEvent(pos) in events.iter() {
+            if !map.deployment_tiles.contains(pos) {
+                continue;
+            }
+
+            if let Some((unit_entity, class_name)) = deployment.deployed.remove(pos) {
+                commands.entity(unit_entity).despawn_recursive();
+                deployment.undeployed.insert(0, class_name);
+                continue;
+            }
+
+            if deployment.undeployed.is_empty() {
+                continue;
+            }
+
+            let class_name = deployment.undeployed.remove(0);
+            let class = unit_classes
+                .get(&class_name, &classes)
+                .expect("No such unit class");
+
+            let unit_entity = Unit::spawn(&mut commands, map, class, *pos, Faction::Player);
+            deployment.deployed.insert(*pos, (unit_entity, class_name));
+        }
+    }
+
+    /// Starts the battle on `InputAction::Start`, as long as at least one unit is deployed
+    fn begin_battle(
+        mut actions: ResMut<Input<InputAction>>,
+        deployment: Res<Deployment>,
+        mut command_events: EventWriter<CommandEvent>,
+    ) {
+        if deployment.deployed.is_empty() {
+            return;
+        }
+
+        if actions.clear_just_pressed(InputAction::Start) {
+            command_events.send(CommandEvent(BattleCommand::BeginBattle));
+        }
+    }
+}
+
+/// Marks the tiles the player can deploy their units on
+pub struct DeploymentMarkerPlugin;
+
+impl Plugin for DeploymentMarkerPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(DeploymentGraphics::default())
+            .add_startup_system(Self::load_marker_graphic)
+            .add_system_set(
+                SystemSet::on_update(AppState::Deployment).with_system(Self::mark_deployment_tiles),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Deployment).with_system(Self::remove_markers),
+            );
+    }
+}
+
+impl DeploymentMarkerPlugin {
+    fn load_marker_graphic(assets: Res<AssetServer>, mut graphics: ResMut<DeploymentGraphics>) {
+        graphics.marker = assets.load("tiles/tile_deploy.png");
+    }
+
+    fn mark_deployment_tiles(
+        mut commands: Commands,
+        map_query: Query<&Map, Added<Map>>,
+        graphics: Res<DeploymentGraphics>,
+    ) {
+        let map = match map_query.get_single() {
+            Ok(map) => map,
+            Err(_) => return,
+        };
+
+        for pos in map.deployment_tiles.iter() {
+            let tile_entity = match map.tile(*pos) {
+                Some(tile_entity) => tile_entity,
+                None => continue,
+            };
+
+            spawn_overlay(
+                &mut commands,
+                map,
+                tile_entity,
+                OverlayLayer::Deployment,
+                graphics.marker.clone(),
+                default(),
+            )
+            .insert(DeploymentMarker);
+        }
+    }
+
+    fn remove_markers(mut commands: Commands, marker_query: Query<Entity, With<DeploymentMarker>>) {
+        for entity in marker_query.iter() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
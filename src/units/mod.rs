@@ -1,16 +1,60 @@
+mod actions;
+mod ai;
+mod animation;
+mod class;
+mod combat;
+mod deployment;
 mod movement;
+mod threat;
 mod unit;
 
+use actions::ActionPlugin;
+pub use actions::{Action, ActionQueue, QueueActionsEvent};
+pub use ai::AiControlled;
+use ai::AiPlugin;
+use animation::AnimationPlugin;
 use bevy::prelude::PluginGroup;
-use movement::MovementPlugin;
-pub use movement::ValidMove;
-use unit::{Direction, UnitPlugin};
-pub use unit::{SelectMode, SelectedUnit, Unit};
+use class::UnitClassPlugin;
+pub use class::{Ability, MovementProfile, UnitClass, UnitClasses};
+use combat::CombatPlugin;
+pub use combat::{UnitDamagedEvent, UnitDefeatedEvent};
+pub use deployment::DeployEvent;
+use deployment::{DeploymentMarkerPlugin, DeploymentPlugin};
+use movement::{MovementPlugin, PathPreviewPlugin, ValidMoveHighlightPlugin};
+pub use movement::{
+    MovementStarted, PreviousMove, StepCompleted, UndoMoveEvent, ValidMove, Waypoints,
+};
+pub use threat::DangerZone;
+use threat::ThreatPlugin;
+use unit::UnitPlugin;
+pub use unit::{Direction, Faction, SelectMode, SelectedUnit, Unit, UnitName};
 
+/// Units and everything they can do, without any graphics
 pub struct UnitPluginGroup;
 
 impl PluginGroup for UnitPluginGroup {
     fn build(&mut self, group: &mut bevy::app::PluginGroupBuilder) {
-        group.add(UnitPlugin).add(MovementPlugin);
+        group
+            .add(UnitClassPlugin)
+            .add(UnitPlugin)
+            .add(DeploymentPlugin)
+            .add(MovementPlugin)
+            .add(ActionPlugin)
+            .add(CombatPlugin)
+            .add(AiPlugin);
+    }
+}
+
+/// Draws units and the information the player needs to command them
+pub struct UnitGraphicsPluginGroup;
+
+impl PluginGroup for UnitGraphicsPluginGroup {
+    fn build(&mut self, group: &mut bevy::app::PluginGroupBuilder) {
+        group
+            .add(AnimationPlugin)
+            .add(DeploymentMarkerPlugin)
+            .add(ValidMoveHighlightPlugin)
+            .add(PathPreviewPlugin)
+            .add(ThreatPlugin);
     }
 }
@@ -1,3 +1,9 @@
 mod movement_plugin;
+mod path_preview;
 
-pub use movement_plugin::{ChangeFacingEvent, MovementPlugin, ValidMove};
+pub(crate) use movement_plugin::get_facing;
+pub use movement_plugin::{
+    ChangeFacingEvent, MovementFinished, MovementPlugin, MovementStarted, PreviousMove,
+    StepCompleted, UndoMoveEvent, ValidMove, ValidMoveHighlightPlugin, Waypoints,
+};
+pub use path_preview::PathPreviewPlugin;
@@ -2,18 +2,96 @@ use bevy::prelude::*;
 use std::collections::VecDeque;
 
 use crate::{
-    tile_map::{Map, Pos, SetPathEvent},
-    units::unit::{SelectedUnit, Unit},
+    command::{BattleCommand, CommandEvent},
+    controls::InputAction,
+    game_speed::GameSpeed,
+    state::AppState,
+    tile_map::{
+        spawn_overlay, DeselectUnitEvent, Map, OverlayLayer, Pos, SelectUnitEvent, SetPathEvent,
+    },
+    turns::TurnState,
+    units::{
+        actions::ActionQueue,
+        animation::{Animation, Animator, WALK_REFERENCE_SPEED},
+        unit::{Faction, SelectedUnit, Unit},
+    },
 };
 
 use super::super::Direction;
 
+/// How long a unit pauses for after hopping up or down, in (game speed adjusted) seconds
+const LANDING_PAUSE: f32 = 0.1;
+
 #[derive(Component)]
 pub(super) struct Moving {
     pub(super) path: VecDeque<Pos>,
+    /// the step currently being taken towards `path[0]`
+    step: Option<Step>,
+    /// time left before the unit carries on after landing a hop
+    landing: Option<Timer>,
+}
+
+impl Moving {
+    pub(super) fn new(path: Vec<Pos>) -> Moving {
+        Moving {
+            path: VecDeque::from(path),
+            step: None,
+            landing: None,
+        }
+    }
+}
+
+/// A single move from the centre of one tile to the centre of the next
+struct Step {
+    from: Vec3,
+    to: Vec3,
+    /// 0.0 at `from`, 1.0 at `to`
+    progress: f32,
+    /// peak height of the hop above a straight line, or 0.0 when not changing elevation
+    hop_height: f32,
+}
+
+impl Step {
+    fn translation(&self) -> Vec3 {
+        let mut translation = self.from.lerp(self.to, self.progress);
+        // parabola through both ends that peaks at `hop_height` halfway along
+        translation.y += self.hop_height * 4.0 * self.progress * (1.0 - self.progress);
+
+        if self.hop_height > 0.0 {
+            // draw in front of both tiles for the whole hop so the unit doesn't clip
+            // through the face of the tile it's jumping onto
+            translation.z = self.from.z.max(self.to.z);
+        }
+
+        translation
+    }
 }
 
 pub struct ChangeFacingEvent(pub Entity, pub Direction);
+
+/// Sent when a unit sets off along a new route
+pub struct MovementStarted(pub Entity);
+/// Sent each time a moving unit arrives on the next tile of its route
+pub struct StepCompleted(pub Entity, pub Pos);
+/// Sent when a unit stops moving, whether it reached the end of its route or not
+pub struct MovementFinished(pub Entity);
+/// Take back the most recent move that can still be undone
+pub struct UndoMoveEvent;
+
+/// Where a unit was before its most recent move, so that the move can be undone.
+///
+/// Anything that commits a unit to its new position (attacking, revealing new information
+/// etc) should remove this component.
+#[derive(Component)]
+pub struct PreviousMove {
+    pos: Pos,
+    tile: Entity,
+    facing: Direction,
+}
+
+/// Units in the order they were moved, most recent last
+#[derive(Default)]
+struct MoveHistory(Vec<Entity>);
 #[derive(Default)]
 struct ValidMoveGraphics {
     overlay: Handle<Image>,
@@ -23,140 +101,343 @@ struct ValidMoveOverlay;
 
 #[derive(Component)]
 pub struct ValidMove;
+
+/// Tiles the selected unit must pass through, in order, on its way to its destination
+#[derive(Default)]
+pub struct Waypoints(pub Vec<Pos>);
 pub struct MovementPlugin;
 
 impl Plugin for MovementPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(ValidMoveGraphics::default())
+        app.insert_resource(Waypoints::default())
+            .insert_resource(MoveHistory::default())
             .add_event::<ChangeFacingEvent>()
-            .add_startup_system(Self::load_overlay_graphic)
-            .add_system(Self::highlight_valid_moves.after("click_tile"))
-            .add_system(Self::set_unit_path)
-            .add_system(Self::move_units);
+            .add_event::<MovementStarted>()
+            .add_event::<StepCompleted>()
+            .add_event::<MovementFinished>()
+            .add_event::<UndoMoveEvent>()
+            .add_system_set(
+                SystemSet::on_update(AppState::Battle)
+                    .with_system(Self::clear_waypoints.after("apply_commands"))
+                    .with_system(Self::set_unit_path)
+                    .with_system(Self::move_units)
+                    .with_system(Self::request_undo.before("apply_commands"))
+                    .with_system(
+                        Self::undo_move
+                            .after(Self::move_units)
+                            .after("apply_commands"),
+                    ),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::Results).with_system(Self::reset));
     }
 }
 
 impl MovementPlugin {
-    fn load_overlay_graphic(assets: Res<AssetServer>, mut graphics: ResMut<ValidMoveGraphics>) {
-        let handle = assets.load("tiles/tile_overlay.png");
+    /// Forgets the last battle's waypoints and move history
+    fn reset(mut waypoints: ResMut<Waypoints>, mut history: ResMut<MoveHistory>) {
+        waypoints.0.clear();
+        history.0.clear();
+    }
 
-        graphics.overlay = handle;
+    fn clear_waypoints(
+        mut waypoints: ResMut<Waypoints>,
+        mut select_events: EventReader<SelectUnitEvent>,
+        mut deselect_events: EventReader<DeselectUnitEvent>,
+    ) {
+        let selection_changed = select_events.iter().count() + deselect_events.iter().count() > 0;
+
+        if selection_changed && !waypoints.0.is_empty() {
+            waypoints.0.clear();
+        }
     }
 
-    fn highlight_valid_moves(
+    fn set_unit_path(
         mut commands: Commands,
-        selected_unit: Res<SelectedUnit>,
-        unit_query: Query<&Unit>,
+        mut events: EventReader<SetPathEvent>,
+        mut history: ResMut<MoveHistory>,
+        mut unit_query: Query<&mut Unit>,
         map_query: Query<&Map>,
-        overlay_query: Query<Entity, With<ValidMoveOverlay>>,
-        valid_move_query: Query<Entity, With<ValidMove>>,
-        graphics: Res<ValidMoveGraphics>,
+        mut started_events: EventWriter<MovementStarted>,
+        mut finished_events: EventWriter<MovementFinished>,
     ) {
-        if selected_unit.is_none() {
-            for entity in overlay_query.iter() {
-                commands.entity(entity).despawn();
-            }
-
-            for entity in valid_move_query.iter() {
-                commands.entity(entity).remove::<ValidMove>();
-            }
+        let map = map_query.get_single().expect("Not exactly one map");
 
-            return;
-        }
-        let map = map_query.get_single().expect("Not exactly one map!");
+        for SetPathEvent(unit_entity, route) in events.iter() {
+            let mut unit = unit_query.get_mut(*unit_entity).unwrap();
 
-        if let SelectedUnit::Some { entity, mode: _ } = *selected_unit {
-            let unit = unit_query.get(entity).expect("No entity for selected unit");
-            let valid_moves = unit.get_valid_moves(map);
+            if unit.has_moved || !unit.is_valid_route(route, map) {
+                warn!("Ignoring invalid route {:?}", route);
+                // so that anything waiting on this unit to move isn't left hanging
+                finished_events.send(MovementFinished(*unit_entity));
+                continue;
+            }
 
-            for pos in valid_moves.iter() {
-                let tile_entity = map.tiles.get(pos).expect("No tile for entity");
+            commands
+                .entity(*unit_entity)
+                .insert(Moving::new(route.clone()))
+                .insert(PreviousMove {
+                    pos: unit.pos,
+                    tile: unit.tile,
+                    facing: unit.facing,
+                });
 
-                let overlay = commands
-                    .spawn_bundle(SpriteBundle {
-                        texture: graphics.overlay.clone(),
-                        transform: Transform::from_xyz(0.0, 8.0, 0.0001),
-                        ..default()
-                    })
-                    .insert(ValidMoveOverlay)
-                    .id();
+            unit.has_moved = true;
+            history.0.push(*unit_entity);
+            started_events.send(MovementStarted(*unit_entity));
+        }
+    }
 
-                commands
-                    .entity(*tile_entity)
-                    .insert(ValidMove)
-                    .add_child(overlay);
-            }
+    /// Undoes the player's last move on `InputAction::Undo`, once nothing is moving
+    fn request_undo(
+        actions: Res<Input<InputAction>>,
+        turn_state: Res<TurnState>,
+        queue_query: Query<&ActionQueue>,
+        mut command_events: EventWriter<CommandEvent>,
+    ) {
+        if actions.just_pressed(InputAction::Undo)
+            && turn_state.phase == Faction::Player
+            && queue_query.iter().all(|queue| queue.is_idle())
+        {
+            command_events.send(CommandEvent(BattleCommand::UndoMove));
         }
     }
 
-    fn set_unit_path(
+    /// Snaps the most recently moved unit back to where it was before moving, as long as
+    /// that move can still be undone
+    fn undo_move(
         mut commands: Commands,
-        mut events: EventReader<SetPathEvent>,
-        mut unit_query: Query<(&mut Unit, Entity)>,
+        mut events: EventReader<UndoMoveEvent>,
+        mut history: ResMut<MoveHistory>,
+        mut unit_query: Query<(&mut Unit, &mut Transform, &mut Animator, &PreviousMove)>,
         map_query: Query<&Map>,
+        mut facing_events: EventWriter<ChangeFacingEvent>,
+        mut finished_events: EventWriter<MovementFinished>,
     ) {
+        if events.iter().count() == 0 {
+            return;
+        }
+
         let map = map_query.get_single().expect("Not exactly one map");
 
-        for SetPathEvent(unit_entity, target_pos) in events.iter() {
-            let (unit, entity) = unit_query.get_mut(*unit_entity).unwrap();
+        // skip over units whose moves have since been committed (or that no longer exist)
+        while let Some(entity) = history.0.pop() {
+            if let Ok((mut unit, mut transform, mut animator, previous)) =
+                unit_query.get_mut(entity)
+            {
+                unit.pos = previous.pos;
+                unit.tile = previous.tile;
+                unit.has_moved = false;
+                transform.translation = map.world_pos_to_unit_screen_pos_absolute(previous.pos);
+                animator.play(Animation::Idle);
+                facing_events.send(ChangeFacingEvent(entity, previous.facing));
 
-            commands.entity(entity).insert(Moving {
-                path: VecDeque::from(unit.get_path(*target_pos, map)),
-            });
+                commands
+                    .entity(entity)
+                    .remove::<Moving>()
+                    .remove::<PreviousMove>();
+                finished_events.send(MovementFinished(entity));
+
+                return;
+            }
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn move_units(
         mut commands: Commands,
-        mut moving_unit_query: Query<(&mut Unit, &mut Transform, &mut Moving, Entity)>,
+        time: Res<Time>,
+        game_speed: Res<GameSpeed>,
+        mut moving_unit_query: Query<(
+            &mut Unit,
+            &mut Transform,
+            &mut Moving,
+            &mut Animator,
+            Entity,
+        )>,
         map_query: Query<&Map>,
         mut event: EventWriter<ChangeFacingEvent>,
+        mut step_events: EventWriter<StepCompleted>,
+        mut finished_events: EventWriter<MovementFinished>,
     ) {
         let map = map_query.get_single().expect("Not exactly one map");
-        for (mut unit, mut transform, mut moving, entity) in moving_unit_query.iter_mut() {
+        let delta = time.delta().mul_f32(game_speed.multiplier());
+
+        for (mut unit, mut transform, mut moving, mut animator, entity) in
+            moving_unit_query.iter_mut()
+        {
+            // stay put for a moment after landing a hop
+            if let Some(landing) = moving.landing.as_mut() {
+                if !landing.tick(delta).finished() {
+                    continue;
+                }
+                moving.landing = None;
+            }
+
+            // when unit reaches last tile, stop moving
+            if moving.path.is_empty() {
+                commands.entity(entity).remove::<Moving>();
+                animator.set_speed(1.0);
+                animator.play(Animation::Idle);
+                finished_events.send(MovementFinished(entity));
+                continue;
+            }
+
+            animator.set_speed(unit.move_speed / WALK_REFERENCE_SPEED);
+            animator.play(Animation::Walk);
+
             let next = moving.path[0];
-            let next_tile_entity = map.tiles.get(&next).expect("No tile at next pos");
 
-            let facing = get_facing(unit.pos, next);
-            event.send(ChangeFacingEvent(entity, facing));
+            if moving.step.is_none() {
+                event.send(ChangeFacingEvent(entity, get_facing(unit.pos, next)));
 
-            let next_pos_isometric = map.world_pos_to_unit_screen_pos_absolute(next);
-            let move_vector =
-                (next_pos_isometric - transform.translation).normalize() * unit.move_speed;
-            let next_translation = transform.translation + move_vector;
+                let elevation_change = (next.y - unit.pos.y).abs();
+                moving.step = Some(Step {
+                    from: transform.translation,
+                    to: map.world_pos_to_unit_screen_pos_absolute(next),
+                    progress: 0.0,
+                    hop_height: elevation_change * map.tile_height(),
+                });
+            }
+
+            let step = moving.step.as_mut().expect("No step in progress");
+            let step_length = step.from.truncate().distance(step.to.truncate());
+            let distance_moved = unit.move_speed * delta.as_secs_f32();
 
-            *transform.translation = *next_translation;
+            // clamp progress so that the unit lands exactly on the tile rather than overshooting
+            step.progress = if step_length > 0.0 {
+                (step.progress + distance_moved / step_length).min(1.0)
+            } else {
+                1.0
+            };
+
+            transform.translation = step.translation();
 
             // when unit reaches next tile,
-            if next_translation.distance(next_pos_isometric) < 0.5 {
+            if step.progress >= 1.0 {
+                let hopped = step.hop_height > 0.0;
+                transform.translation = step.to;
+                moving.step = None;
+
                 // pop it from path
                 moving.path.pop_front();
-                // update current tile for facing calculation on next frame
-                unit.tile = *next_tile_entity;
+                // update current tile for facing calculation on next step
+                unit.tile = map.tile(next).expect("No tile at next pos");
                 unit.pos = next;
-            }
+                step_events.send(StepCompleted(entity, next));
 
-            // when unit reaches last tile, stop moving
-            if moving.path.is_empty() {
-                commands.entity(entity).remove::<Moving>();
+                if hopped {
+                    moving.landing = Some(Timer::from_seconds(LANDING_PAUSE, false));
+                }
             }
         }
     }
 }
 
-fn get_facing(current: Pos, next: Pos) -> Direction {
-    if next.x > current.x && next.z == current.z {
-        return Direction::NorthEast;
+/// Tints the tiles the selected unit can move to, marking them as `ValidMove`s
+pub struct ValidMoveHighlightPlugin;
+
+impl Plugin for ValidMoveHighlightPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ValidMoveGraphics::default())
+            .add_startup_system(Self::load_overlay_graphic)
+            .add_system_set(
+                SystemSet::on_update(AppState::Battle)
+                    .with_system(Self::highlight_valid_moves.after("click_tile")),
+            );
     }
-    if next.x < current.x && next.z == current.z {
-        return Direction::SouthWest;
+}
+
+impl ValidMoveHighlightPlugin {
+    fn load_overlay_graphic(assets: Res<AssetServer>, mut graphics: ResMut<ValidMoveGraphics>) {
+        let handle = assets.load("tiles/tile_overlay.png");
+
+        graphics.overlay = handle;
     }
-    if next.z > current.z && next.x == current.x {
-        return Direction::NorthWest;
+
+    /// Marks the tiles the selected unit can move to, clearing and rebuilding the marks
+    /// whenever a different unit is selected or the selected unit moves
+    #[allow(clippy::too_many_arguments)]
+    fn highlight_valid_moves(
+        mut commands: Commands,
+        mut highlighted: Local<Option<(Entity, Pos, bool)>>,
+        selected_unit: Res<SelectedUnit>,
+        unit_query: Query<&Unit>,
+        map_query: Query<&Map>,
+        overlay_query: Query<Entity, With<ValidMoveOverlay>>,
+        valid_move_query: Query<Entity, With<ValidMove>>,
+        graphics: Res<ValidMoveGraphics>,
+    ) {
+        let selected = match *selected_unit {
+            SelectedUnit::Some { entity, mode: _ } => unit_query
+                .get(entity)
+                .ok()
+                .map(|unit| (entity, unit.pos, unit.has_moved)),
+            SelectedUnit::None => None,
+        };
+
+        if selected == *highlighted {
+            return;
+        }
+        *highlighted = selected;
+
+        for entity in overlay_query.iter() {
+            commands.entity(entity).despawn();
+        }
+
+        for entity in valid_move_query.iter() {
+            commands.entity(entity).remove::<ValidMove>();
+        }
+
+        let (entity, _, has_moved) = match selected {
+            Some(selected) => selected,
+            None => return,
+        };
+
+        // units only get one move per phase
+        if has_moved {
+            return;
+        }
+
+        let map = map_query.get_single().expect("Not exactly one map!");
+        let unit = unit_query.get(entity).expect("No entity for selected unit");
+
+        for pos in unit.get_valid_moves(map).iter() {
+            let tile_entity = map.tile(*pos).expect("No tile for entity");
+
+            spawn_overlay(
+                &mut commands,
+                map,
+                tile_entity,
+                OverlayLayer::ValidMove,
+                graphics.overlay.clone(),
+                default(),
+            )
+            .insert(ValidMoveOverlay);
+
+            commands.entity(tile_entity).insert(ValidMove);
+        }
     }
-    if next.z < current.z && next.x == current.x {
-        return Direction::SouthEast;
+}
+
+/// Returns the direction to face when moving (or attacking) from `current` towards `next`,
+/// going by whichever of the x and z axes `next` is furthest along
+pub(crate) fn get_facing(current: Pos, next: Pos) -> Direction {
+    let x_offset = f32::from(next.x - current.x);
+    let z_offset = f32::from(next.z - current.z);
+
+    if x_offset == 0.0 && z_offset == 0.0 {
+        return Direction::SouthWest;
     }
 
-    Direction::SouthWest
+    if x_offset.abs() >= z_offset.abs() {
+        if x_offset > 0.0 {
+            Direction::NorthEast
+        } else {
+            Direction::SouthWest
+        }
+    } else if z_offset > 0.0 {
+        Direction::NorthWest
+    } else {
+        Direction::SouthEast
+    }
 }
@@ -0,0 +1,184 @@
+use bevy::prelude::*;
+
+use crate::{
+    state::AppState,
+    tile_map::{spawn_overlay, ActiveTile, Map, OverlayLayer, Pos, Tile},
+    units::unit::{SelectMode, SelectedUnit, Unit},
+};
+
+use super::{super::Direction, movement_plugin::get_facing, ValidMove, Waypoints};
+
+#[derive(Default)]
+struct PathPreviewGraphics {
+    /// half a path segment, running from the centre of a tile to its north east edge
+    segment: Handle<Image>,
+    /// arrow head pointing north east
+    arrow: Handle<Image>,
+    step_up: Handle<Image>,
+    step_down: Handle<Image>,
+}
+
+#[derive(Component)]
+struct PathPreviewSprite;
+
+/// Draws the route a selected unit will take to the hovered `ValidMove` tile, via any `Waypoints`
+pub struct PathPreviewPlugin;
+
+impl Plugin for PathPreviewPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PathPreviewGraphics::default())
+            .add_startup_system(Self::load_graphics)
+            .add_system_set(SystemSet::on_update(AppState::Battle).with_system(Self::preview_path));
+    }
+}
+
+impl PathPreviewPlugin {
+    fn load_graphics(assets: Res<AssetServer>, mut graphics: ResMut<PathPreviewGraphics>) {
+        graphics.segment = assets.load("ui/path/segment.png");
+        graphics.arrow = assets.load("ui/path/arrow.png");
+        graphics.step_up = assets.load("ui/path/step_up.png");
+        graphics.step_down = assets.load("ui/path/step_down.png");
+    }
+
+    /// Rebuilds the path preview whenever the selected unit, hovered destination or
+    /// waypoints change
+    #[allow(clippy::too_many_arguments)]
+    fn preview_path(
+        mut commands: Commands,
+        mut previewed: Local<Option<(Entity, Option<Pos>)>>,
+        active_tile: Res<ActiveTile>,
+        selected_unit: Res<SelectedUnit>,
+        waypoints: Res<Waypoints>,
+        unit_query: Query<&Unit>,
+        valid_move_query: Query<&Tile, With<ValidMove>>,
+        preview_query: Query<Entity, With<PathPreviewSprite>>,
+        map_query: Query<&Map>,
+        rotated_query: Query<(), Changed<Map>>,
+        graphics: Res<PathPreviewGraphics>,
+    ) {
+        let preview = match *selected_unit {
+            SelectedUnit::Some {
+                entity,
+                mode: SelectMode::Move,
+            } => {
+                let hovered = active_tile
+                    .0
+                    .and_then(|tile_entity| valid_move_query.get(tile_entity).ok())
+                    .map(|tile| tile.pos);
+
+                Some((entity, hovered))
+            }
+            _ => None,
+        };
+
+        if preview == *previewed && !waypoints.is_changed() && rotated_query.is_empty() {
+            return;
+        }
+        *previewed = preview;
+
+        for entity in preview_query.iter() {
+            commands.entity(entity).despawn();
+        }
+
+        let (unit_entity, hovered) = match preview {
+            Some(preview) => preview,
+            None => return,
+        };
+
+        let map = map_query.get_single().expect("Not exactly one map");
+        let unit = unit_query
+            .get(unit_entity)
+            .expect("No entity for selected unit");
+
+        // when not hovering a destination, show the route through the waypoints so far
+        let route = match (hovered, waypoints.0.split_last()) {
+            (Some(target_pos), _) => unit.get_route(&waypoints.0, target_pos, map),
+            (None, Some((last, rest))) => unit.get_route(rest, *last, map),
+            (None, None) => None,
+        };
+
+        let path = match route {
+            Some(path) => path,
+            None => return,
+        };
+
+        let mut previous = unit.pos;
+
+        for (i, pos) in path.iter().enumerate() {
+            let direction = map.rotation.screen_direction(get_facing(previous, *pos));
+            let previous_tile = map.tile(previous).expect("No tile for path pos");
+            let tile = map.tile(*pos).expect("No tile for path pos");
+
+            // each step is drawn as two halves: leaving the previous tile and entering this one
+            spawn_preview_sprite(
+                &mut commands,
+                map,
+                previous_tile,
+                graphics.segment.clone(),
+                direction,
+            );
+            spawn_preview_sprite(
+                &mut commands,
+                map,
+                tile,
+                graphics.segment.clone(),
+                direction.opposite(),
+            );
+
+            if pos.y > previous.y {
+                spawn_preview_sprite(
+                    &mut commands,
+                    map,
+                    tile,
+                    graphics.step_up.clone(),
+                    Direction::NorthEast,
+                );
+            } else if pos.y < previous.y {
+                spawn_preview_sprite(
+                    &mut commands,
+                    map,
+                    tile,
+                    graphics.step_down.clone(),
+                    Direction::NorthEast,
+                );
+            }
+
+            if i == path.len() - 1 {
+                spawn_preview_sprite(&mut commands, map, tile, graphics.arrow.clone(), direction);
+            }
+
+            previous = *pos;
+        }
+    }
+}
+
+/// Spawns a preview sprite on top of a tile, flipping it so that a north east facing
+/// image points in `direction`
+fn spawn_preview_sprite(
+    commands: &mut Commands,
+    map: &Map,
+    tile_entity: Entity,
+    texture: Handle<Image>,
+    direction: Direction,
+) {
+    let (flip_x, flip_y) = match direction {
+        Direction::NorthEast => (false, false),
+        Direction::NorthWest => (true, false),
+        Direction::SouthEast => (false, true),
+        Direction::SouthWest => (true, true),
+    };
+
+    spawn_overlay(
+        commands,
+        map,
+        tile_entity,
+        OverlayLayer::PathPreview,
+        texture,
+        Sprite {
+            flip_x,
+            flip_y,
+            ..default()
+        },
+    )
+    .insert(PathPreviewSprite);
+}
@@ -0,0 +1,176 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+
+use crate::{
+    controls::InputAction,
+    state::AppState,
+    tile_map::{spawn_overlay, Map, OverlayLayer, Pos},
+};
+
+use super::{
+    class::Ability,
+    unit::{Faction, Unit},
+};
+
+#[derive(Default)]
+struct DangerZoneGraphics {
+    overlay: Handle<Image>,
+}
+
+#[derive(Component)]
+struct DangerZoneOverlay;
+
+/// Every tile that an enemy unit can hit next turn.
+///
+/// Threatened tiles are cached per unit so that only units which have changed
+/// (e.g. moved to a new tile) need their reach recomputing.
+#[derive(Default)]
+pub struct DangerZone {
+    pub visible: bool,
+    threats: HashMap<Entity, HashSet<Pos>>,
+    /// Overlay sprite entity for each currently highlighted tile
+    overlays: HashMap<Pos, Entity>,
+}
+
+impl DangerZone {
+    pub fn tiles(&self) -> HashSet<Pos> {
+        self.threats.values().flatten().copied().collect()
+    }
+
+    pub fn is_threatened(&self, pos: Pos) -> bool {
+        self.threats
+            .values()
+            .any(|threatened| threatened.contains(&pos))
+    }
+}
+
+pub struct ThreatPlugin;
+
+impl Plugin for ThreatPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(DangerZoneGraphics::default())
+            .insert_resource(DangerZone::default())
+            .add_startup_system(Self::load_overlay_graphic)
+            .add_system(Self::toggle_danger_zone)
+            .add_system(Self::update_threats)
+            .add_system(
+                Self::draw_danger_zone
+                    .after(Self::toggle_danger_zone)
+                    .after(Self::update_threats),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::Results).with_system(Self::reset));
+    }
+}
+
+impl ThreatPlugin {
+    /// Drops the last battle's threats. The overlay sprites go with the map's tiles.
+    fn reset(mut danger_zone: ResMut<DangerZone>) {
+        danger_zone.threats.clear();
+        danger_zone.overlays.clear();
+    }
+
+    fn load_overlay_graphic(assets: Res<AssetServer>, mut graphics: ResMut<DangerZoneGraphics>) {
+        graphics.overlay = assets.load("tiles/tile_danger.png");
+    }
+
+    fn toggle_danger_zone(actions: Res<Input<InputAction>>, mut danger_zone: ResMut<DangerZone>) {
+        if actions.just_pressed(InputAction::ToggleThreats) {
+            danger_zone.visible = !danger_zone.visible;
+        }
+    }
+
+    /// Recompute threatened tiles for units that have changed since last frame, or for
+    /// every unit if the map itself has changed
+    fn update_threats(
+        mut danger_zone: ResMut<DangerZone>,
+        unit_query: Query<(Entity, &Unit)>,
+        changed_unit_query: Query<(Entity, &Unit), Changed<Unit>>,
+        map_query: Query<&Map>,
+        changed_map_query: Query<(), Changed<Map>>,
+        removed_units: RemovedComponents<Unit>,
+    ) {
+        for entity in removed_units.iter() {
+            danger_zone.threats.remove(&entity);
+        }
+
+        let map = match map_query.get_single() {
+            Ok(map) => map,
+            Err(_) => return,
+        };
+
+        let mut update = |entity: Entity, unit: &Unit| {
+            if unit.faction == Faction::Enemy && unit.can(Ability::Attack) {
+                danger_zone
+                    .threats
+                    .insert(entity, unit.get_threatened_tiles(map));
+            } else if danger_zone.threats.contains_key(&entity) {
+                danger_zone.threats.remove(&entity);
+            }
+        };
+
+        if changed_map_query.is_empty() {
+            for (entity, unit) in changed_unit_query.iter() {
+                update(entity, unit);
+            }
+        } else {
+            for (entity, unit) in unit_query.iter() {
+                update(entity, unit);
+            }
+        }
+    }
+
+    /// Adds and removes overlay sprites so that exactly the threatened tiles are tinted
+    fn draw_danger_zone(
+        mut commands: Commands,
+        mut danger_zone: ResMut<DangerZone>,
+        map_query: Query<&Map>,
+        graphics: Res<DangerZoneGraphics>,
+    ) {
+        if !danger_zone.is_changed() {
+            return;
+        }
+
+        let map = match map_query.get_single() {
+            Ok(map) => map,
+            Err(_) => return,
+        };
+
+        let threatened = if danger_zone.visible {
+            danger_zone.tiles()
+        } else {
+            HashSet::new()
+        };
+
+        danger_zone.overlays.retain(|pos, overlay| {
+            let keep = threatened.contains(pos);
+            if !keep {
+                commands.entity(*overlay).despawn();
+            }
+            keep
+        });
+
+        for pos in threatened.iter() {
+            if danger_zone.overlays.contains_key(pos) {
+                continue;
+            }
+
+            let tile_entity = match map.tile(*pos) {
+                Some(tile_entity) => tile_entity,
+                None => continue,
+            };
+
+            let overlay = spawn_overlay(
+                &mut commands,
+                map,
+                tile_entity,
+                OverlayLayer::DangerZone,
+                graphics.overlay.clone(),
+                default(),
+            )
+            .insert(DangerZoneOverlay)
+            .id();
+            danger_zone.overlays.insert(*pos, overlay);
+        }
+    }
+}
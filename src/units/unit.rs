@@ -1,14 +1,44 @@
-use bevy::{prelude::*, sprite::Anchor};
-use pathfinding::prelude::astar;
-
-use crate::tile_map::{DeselectUnitEvent, Map, Pos, SelectUnitEvent};
+use std::collections::{HashMap, HashSet};
 
-use super::movement::ChangeFacingEvent;
-
-#[derive(Copy, Clone, Debug)]
+use bevy::prelude::*;
+use pathfinding::prelude::astar;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    state::AppState,
+    tile_map::{DeselectUnitEvent, Map, Pos, SelectUnitEvent},
+};
+
+use super::{
+    actions::ActionQueue,
+    animation::Animator,
+    class::{Ability, MovementProfile, UnitClass},
+    combat::Defeated,
+    movement::ChangeFacingEvent,
+};
+
+/// What the player is choosing for the `SelectedUnit`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum SelectMode {
+    /// where to move, which is where a unit starts when it's selected
     Move,
+    /// which of the unit's abilities to use, opened by confirming the unit itself
+    ActionMenu,
+    /// which unit to attack
+    AttackTarget,
+}
+
+impl SelectMode {
+    /// The mode that `InputAction::Cancel` steps back to, or `None` to deselect the unit
+    pub(crate) fn previous(&self) -> Option<SelectMode> {
+        match self {
+            SelectMode::Move => None,
+            SelectMode::ActionMenu => Some(SelectMode::Move),
+            SelectMode::AttackTarget => Some(SelectMode::ActionMenu),
+        }
+    }
 }
+
 #[derive(Copy, Clone, Default, Debug)]
 pub enum SelectedUnit {
     #[default]
@@ -19,133 +49,275 @@ pub enum SelectedUnit {
     },
 }
 
-impl SelectedUnit {
-    pub fn is_some(&self) -> bool {
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Direction {
+    NorthEast,
+    NorthWest,
+    SouthEast,
+    SouthWest,
+}
+
+impl Direction {
+    pub(crate) fn opposite(&self) -> Direction {
         match self {
-            SelectedUnit::None => false,
-            SelectedUnit::Some { entity: _, mode: _ } => true,
+            Direction::NorthEast => Direction::SouthWest,
+            Direction::NorthWest => Direction::SouthEast,
+            Direction::SouthEast => Direction::NorthWest,
+            Direction::SouthWest => Direction::NorthEast,
         }
     }
 
-    pub fn is_none(&self) -> bool {
-        !self.is_some()
+    /// The direction a quarter turn clockwise from this one, as seen from above
+    pub(crate) fn clockwise(&self) -> Direction {
+        match self {
+            Direction::NorthEast => Direction::SouthEast,
+            Direction::SouthEast => Direction::SouthWest,
+            Direction::SouthWest => Direction::NorthWest,
+            Direction::NorthWest => Direction::NorthEast,
+        }
     }
 }
 
-#[derive(Default)]
-struct VillagerSprites {
-    texture_atlas: Handle<TextureAtlas>,
-    sw_index: usize,
-    se_index: usize,
-    ne_index: usize,
-    nw_index: usize,
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum Faction {
+    Player,
+    Enemy,
 }
 
-#[derive(Clone, Copy)]
-pub enum Direction {
-    NorthEast,
-    NorthWest,
-    SouthEast,
-    SouthWest,
-}
+/// A unit's unique name, for units that objectives refer to (e.g. a boss to defeat)
+#[derive(Component, Clone, Debug)]
+pub struct UnitName(pub String);
+
 #[derive(Component)]
 pub struct Unit {
+    /// name of the `UnitClass` this unit was spawned from
+    pub(crate) class: String,
     pub(crate) pos: Pos,
     pub tile: Entity,
+    pub(crate) faction: Faction,
+    pub(crate) hp: u32,
+    pub(crate) max_hp: u32,
+    pub(crate) attack: u32,
     pub(crate) move_speed: f32,
     pub(crate) move_distance: usize,
+    /// how far away (in tiles, ignoring elevation) this unit can attack from
+    pub(crate) attack_range: usize,
+    pub(crate) movement: MovementProfile,
+    pub(crate) abilities: Vec<Ability>,
     pub(crate) facing: Direction,
-    pub(crate) sprites: Sprites,
-    anchor: Anchor,
-}
-
-pub(crate) struct Sprites {
-    texture_atlas: Handle<TextureAtlas>,
-    north_east: usize,
-    north_west: usize,
-    south_east: usize,
-    south_west: usize,
+    /// whether this unit has moved during its faction's current phase
+    pub(crate) has_moved: bool,
+    /// whether this unit has attacked during its faction's current phase, which ends its turn
+    pub(crate) has_acted: bool,
 }
 
 impl Unit {
+    /// Returns every tile this unit can step to from `pos` in one move
+    pub(crate) fn get_frontier(&self, pos: Pos, map: &Map) -> Vec<Pos> {
+        map.get_frontier(pos, self.movement.max_climb, self.movement.max_drop)
+    }
+
+    pub(crate) fn can(&self, ability: Ability) -> bool {
+        self.abilities.contains(&ability)
+    }
+
+    /// Returns every tile this unit can reach within its `move_distance`, paying each tile's
+    /// terrain `move_cost` to step onto it. Each tile is returned once, cheapest to reach first.
     pub(crate) fn get_valid_moves(&self, map: &Map) -> Vec<Pos> {
         let mut valid_moves: Vec<Pos> = vec![];
+        let mut costs = HashMap::from([(self.pos, 0)]);
+
+        // tiles waiting to be expanded, by what it cost to reach them. Expanding the cheapest
+        // first means each tile is reached by its cheapest route before it's returned.
+        let mut frontiers: Vec<Vec<Pos>> = vec![vec![]; self.move_distance + 1];
+        frontiers[0].push(self.pos);
+
+        for cost in 0..=self.move_distance {
+            for pos in std::mem::take(&mut frontiers[cost]) {
+                // since reached more cheaply
+                if costs[&pos] < cost {
+                    continue;
+                }
+
+                if cost > 0 {
+                    valid_moves.push(pos);
+                }
+
+                for next in self.get_frontier(pos, map) {
+                    let next_cost = cost + map.move_cost(next);
+                    if next_cost <= self.move_distance
+                        && costs.get(&next).is_none_or(|known| next_cost < *known)
+                    {
+                        costs.insert(next, next_cost);
+                        frontiers[next_cost].push(next);
+                    }
+                }
+            }
+        }
 
-        // for each allowed move, get the frontier from each tile reachable by previous moves
-        // return all reachable tiles
-        let mut prev_frontier: Vec<Pos> = vec![self.pos];
+        valid_moves
+    }
 
-        for _depth in 0..self.move_distance {
-            let mut iteration_frontier = vec![];
+    /// Returns every tile this unit could attack next turn, i.e. all tiles within
+    /// `attack_range` of somewhere it can move to (or its current position)
+    pub(crate) fn get_threatened_tiles(&self, map: &Map) -> HashSet<Pos> {
+        let mut standing_positions = self
+            .get_valid_moves(map)
+            .into_iter()
+            .collect::<HashSet<Pos>>();
+        standing_positions.insert(self.pos);
+
+        let attack_range = self.attack_range as f32;
+
+        map.tiles()
+            .map(|(pos, _)| pos)
+            .filter(|pos| !map.is_pos_covered(*pos))
+            .filter(|pos| {
+                standing_positions.iter().any(|standing_pos| {
+                    let distance = standing_pos.ground_distance(pos);
+                    distance >= 1.0 && distance <= attack_range
+                })
+            })
+            .collect()
+    }
 
-            for pos in prev_frontier {
-                iteration_frontier.append(&mut map.get_frontier(pos));
-            }
+    /// Returns the path from `start` to `target_pos`, excluding `start`
+    fn get_path_between(&self, start: Pos, target_pos: Pos, map: &Map) -> Option<Vec<Pos>> {
+        let (mut path, _) = astar(
+            &start,
+            |p| {
+                self.get_frontier(*p, map)
+                    .into_iter()
+                    .map(|pos| (pos, map.move_cost(pos)))
+                    .collect::<Vec<(Pos, usize)>>()
+            },
+            |p| (p.distance(&target_pos) / 3.0) as usize,
+            |p| p == &target_pos,
+        )?;
+
+        path.remove(0);
 
-            // clone because append leaves empty vec behind!
-            valid_moves.append(&mut iteration_frontier.clone());
-            prev_frontier = iteration_frontier;
+        Some(path)
+    }
+
+    /// Returns a route to `target_pos` that passes through each of `waypoints` in order,
+    /// or `None` if there is no such route within this unit's movement budget
+    pub(crate) fn get_route(
+        &self,
+        waypoints: &[Pos],
+        target_pos: Pos,
+        map: &Map,
+    ) -> Option<Vec<Pos>> {
+        let mut route = vec![];
+        let mut start = self.pos;
+
+        for pos in waypoints.iter().chain(std::iter::once(&target_pos)) {
+            route.append(&mut self.get_path_between(start, *pos, map)?);
+            start = *pos;
         }
 
-        let mut filtered_moves = valid_moves
-            .into_iter()
-            // remove current position from valid moves
-            .filter(|pos| *pos != self.pos)
-            .collect::<Vec<Pos>>();
+        if self.is_valid_route(&route, map) {
+            Some(route)
+        } else {
+            None
+        }
+    }
 
-        filtered_moves.dedup();
+    /// Returns the route this unit needs to take (via `waypoints`) to get within attack range
+    /// of `target_pos` without ending up on an `occupied` tile.
+    ///
+    /// The route is empty if the unit can already attack from where it's standing, and `None`
+    /// if it can't get in range at all.
+    pub(crate) fn get_attack_route(
+        &self,
+        target_pos: Pos,
+        waypoints: &[Pos],
+        map: &Map,
+        occupied: &HashSet<Pos>,
+    ) -> Option<Vec<Pos>> {
+        let attack_range = self.attack_range as f32;
+
+        if waypoints.is_empty() && self.pos.ground_distance(&target_pos) <= attack_range {
+            return Some(vec![]);
+        }
 
-        filtered_moves
+        self.get_valid_moves(map)
+            .into_iter()
+            .filter(|pos| !occupied.contains(pos))
+            .filter(|pos| pos.ground_distance(&target_pos) <= attack_range)
+            .filter_map(|pos| self.get_route(waypoints, pos, map))
+            .min_by_key(|route| route_cost(route, map))
     }
 
-    pub(crate) fn get_path(&self, target_pos: Pos, map: &Map) -> Vec<Pos> {
-        let (mut path, _) = astar(
-            &self.pos,
-            |p| p.successors(map),
-            |p| (p.distance(&target_pos) / 3.0) as u32,
-            |p| p == &target_pos,
-        )
-        .expect("No path found");
+    /// Returns `true` if every step of `route` is reachable from the step before it
+    /// and the whole route's `move_cost` fits within this unit's `move_distance`
+    pub(crate) fn is_valid_route(&self, route: &[Pos], map: &Map) -> bool {
+        if route.is_empty() || route_cost(route, map) > self.move_distance {
+            return false;
+        }
 
-        path.retain(|c| *c != self.pos);
+        let mut previous = self.pos;
+        for pos in route {
+            if !self.get_frontier(previous, map).contains(pos) {
+                return false;
+            }
+            previous = *pos;
+        }
 
-        path
+        true
     }
 
-    fn spawn_villager(
+    /// Spawns a unit of the given class on the tile at `starting_pos`
+    pub(crate) fn spawn(
         commands: &mut Commands,
-        graphics: &Res<VillagerSprites>,
-        screen_coords: Vec3,
-        tile_entity: Entity,
+        map: &Map,
+        class: &UnitClass,
         starting_pos: Pos,
-    ) {
+        faction: Faction,
+    ) -> Entity {
+        let tile_entity = map.tile(starting_pos).expect("No such tile");
+
         let unit = Unit {
+            class: class.name.clone(),
             tile: tile_entity,
             pos: starting_pos,
+            faction,
             facing: Direction::SouthWest,
-            move_speed: 0.8,
-            move_distance: 3,
-            sprites: Sprites {
-                texture_atlas: graphics.texture_atlas.clone(),
-                north_east: graphics.ne_index,
-                north_west: graphics.nw_index,
-                south_east: graphics.se_index,
-                south_west: graphics.sw_index,
-            },
-            anchor: Anchor::Custom(Vec2::new(0.0, -0.3)),
+            hp: class.stats.max_hp,
+            max_hp: class.stats.max_hp,
+            attack: class.stats.attack,
+            move_speed: class.stats.move_speed,
+            move_distance: class.stats.move_distance,
+            attack_range: class.stats.attack_range,
+            movement: class.movement,
+            abilities: class.abilities.clone(),
+            has_moved: false,
+            has_acted: false,
         };
 
-        let mut sprite = TextureAtlasSprite::new(graphics.sw_index);
-        sprite.anchor = unit.anchor.clone();
+        unit.respawn(commands, map, class)
+    }
+
+    /// Spawns a unit whose state already exists, e.g. from a save file, on the tile at
+    /// its `pos`. `AnimationPlugin` gives it `class`'s sprites when there is something to
+    /// draw them.
+    pub(crate) fn respawn(
+        mut self,
+        commands: &mut Commands,
+        map: &Map,
+        class: &UnitClass,
+    ) -> Entity {
+        self.tile = map.tile(self.pos).expect("No such tile");
+        let screen_coords = map.world_pos_to_unit_screen_pos_absolute(self.pos);
 
         commands
-            .spawn_bundle(SpriteSheetBundle {
-                sprite,
-                texture_atlas: unit.sprites.texture_atlas.clone(),
-                transform: Transform::from_translation(screen_coords),
-                ..default()
-            })
-            .insert(unit);
+            .spawn_bundle(TransformBundle::from_transform(
+                Transform::from_translation(screen_coords),
+            ))
+            .insert(Animator::new(class.sheet.clone(), self.facing))
+            .insert(ActionQueue::default())
+            .insert(self)
+            .id()
     }
 }
 
@@ -153,53 +325,15 @@ pub struct UnitPlugin;
 
 impl Plugin for UnitPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(VillagerSprites::default())
-            .insert_resource(SelectedUnit::default())
-            .add_startup_system(Self::load_unit_graphics)
-            .add_startup_system(Self::spawn_villagers.after(Self::load_unit_graphics))
+        app.insert_resource(SelectedUnit::default())
             .add_system(Self::select_unit)
             .add_system(Self::deselect_unit)
-            .add_system(Self::change_facing);
+            .add_system(Self::change_facing)
+            .add_system_set(SystemSet::on_exit(AppState::Results).with_system(Self::despawn_units));
     }
 }
 
 impl UnitPlugin {
-    fn load_unit_graphics(
-        assets: Res<AssetServer>,
-        mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-        mut graphics: ResMut<VillagerSprites>,
-    ) {
-        let image_handle = assets.load("units/basic-sheet.png");
-        let mut atlas = TextureAtlas::new_empty(image_handle, Vec2::splat(128.0));
-
-        let south_west_index = atlas.add_texture(bevy::sprite::Rect {
-            min: Vec2::splat(0.0),
-            max: Vec2::new(32.0, 48.0),
-        });
-        let south_east_index = atlas.add_texture(bevy::sprite::Rect {
-            min: Vec2::new(32.0, 0.0),
-            max: Vec2::new(64.0, 48.0),
-        });
-        let north_east_index = atlas.add_texture(bevy::sprite::Rect {
-            min: Vec2::new(64.0, 0.0),
-            max: Vec2::new(96.0, 48.0),
-        });
-        let north_west_index = atlas.add_texture(bevy::sprite::Rect {
-            min: Vec2::new(96.0, 0.0),
-            max: Vec2::new(128.0, 48.0),
-        });
-
-        let atlas_handle = texture_atlases.add(atlas);
-
-        *graphics = VillagerSprites {
-            texture_atlas: atlas_handle,
-            sw_index: south_west_index,
-            se_index: south_east_index,
-            nw_index: north_west_index,
-            ne_index: north_east_index,
-        }
-    }
-
     fn select_unit(
         mut selected_unit: ResMut<SelectedUnit>,
         mut events: EventReader<SelectUnitEvent>,
@@ -223,49 +357,284 @@ impl UnitPlugin {
 
     fn change_facing(
         mut events: EventReader<ChangeFacingEvent>,
-        mut unit_query: Query<(&Unit, &mut TextureAtlasSprite)>,
+        mut unit_query: Query<(&mut Unit, &mut Animator)>,
     ) {
         for ChangeFacingEvent(entity, direction) in events.iter() {
-            let (unit, mut sprite) = unit_query.get_mut(*entity).expect("No unit for entity");
-
-            *sprite = match *direction {
-                Direction::NorthEast => TextureAtlasSprite::new(unit.sprites.north_east),
-                Direction::NorthWest => TextureAtlasSprite::new(unit.sprites.north_west),
-                Direction::SouthEast => TextureAtlasSprite::new(unit.sprites.south_east),
-                Direction::SouthWest => TextureAtlasSprite::new(unit.sprites.south_west),
-            };
-            sprite.anchor = Anchor::Custom(Vec2::new(0.0, -0.3))
+            let (mut unit, mut animator) = unit_query.get_mut(*entity).expect("No unit for entity");
+
+            // avoid flagging the unit as changed when it's already facing the right way
+            if unit.facing != *direction {
+                unit.facing = *direction;
+            }
+
+            animator.face(*direction);
         }
     }
 
-    fn spawn_villagers(
+    /// Despawns every unit, knocked out or not, once a battle is over
+    fn despawn_units(
         mut commands: Commands,
-        graphics: Res<VillagerSprites>,
-        map_query: Query<&Map>,
+        mut selected_unit: ResMut<SelectedUnit>,
+        unit_query: Query<Entity, With<Unit>>,
+        defeated_query: Query<Entity, With<Defeated>>,
     ) {
-        let map = map_query.get_single().expect("Not exactly 1 map");
-        let starting_pos1 = Pos::new(4.0, 0.0, 4.0);
-        let starting_pos2 = Pos::new(6.0, 0.0, 4.0);
-
-        let tile_entity1 = map.tiles.get(&starting_pos1).expect("No such tile");
-        let tile_entity2 = map.tiles.get(&starting_pos2).expect("No such tile");
-        let screen_coords1 = map.world_pos_to_unit_screen_pos_absolute(starting_pos1);
-        let screen_coords2 = map.world_pos_to_unit_screen_pos_absolute(starting_pos2);
-
-        Unit::spawn_villager(
-            &mut commands,
-            &graphics,
-            screen_coords1,
-            *tile_entity1,
-            starting_pos1,
+        for entity in unit_query.iter().chain(defeated_query.iter()) {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        *selected_unit = SelectedUnit::None;
+    }
+}
+
+/// The total `move_cost` of every step along `route`
+fn route_cost(route: &[Pos], map: &Map) -> usize {
+    route.iter().map(|pos| map.move_cost(*pos)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::CommandQueue;
+
+    use crate::tile_map::{tile::Terrain, TileDefinition, TileHeight};
+
+    use super::*;
+
+    /// A map of full height grass tiles at each of `positions`
+    fn test_map(positions: &[(f32, f32, f32)]) -> Map {
+        terrain_map(positions, &[])
+    }
+
+    /// A map of full height tiles at each of `positions`, grass unless given in `terrain`
+    fn terrain_map(positions: &[(f32, f32, f32)], terrain: &[((f32, f32, f32), Terrain)]) -> Map {
+        let world = World::new();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+
+        let tiles = positions
+            .iter()
+            .map(|pos| TileDefinition {
+                pos: Pos::from(*pos),
+                height: TileHeight::Full,
+                terrain: terrain
+                    .iter()
+                    .find(|(other, _)| other == pos)
+                    .map_or(Terrain::Grass, |(_, terrain)| *terrain),
+            })
+            .collect::<Vec<_>>();
+
+        Map::from_tiles(&mut commands, &tiles)
+    }
+
+    fn test_unit(
+        pos: (f32, f32, f32),
+        move_distance: usize,
+        max_climb: f32,
+        max_drop: f32,
+    ) -> Unit {
+        Unit {
+            class: "Test".to_string(),
+            pos: Pos::new(pos.0, pos.1, pos.2),
+            tile: Entity::from_raw(0),
+            faction: Faction::Player,
+            hp: 1,
+            max_hp: 1,
+            attack: 1,
+            move_speed: 1.0,
+            move_distance,
+            attack_range: 1,
+            movement: MovementProfile {
+                max_climb,
+                max_drop,
+            },
+            abilities: vec![],
+            facing: Direction::SouthWest,
+            has_moved: false,
+            has_acted: false,
+        }
+    }
+
+    const FLAT_TILES: [(f32, f32, f32); 8] = [
+        (0.0, 0.0, 0.0),
+        (1.0, 0.0, 0.0),
+        (2.0, 0.0, 0.0),
+        (3.0, 0.0, 0.0),
+        (0.0, 0.0, 1.0),
+        (1.0, 0.0, 1.0),
+        (2.0, 0.0, 1.0),
+        (3.0, 0.0, 1.0),
+    ];
+
+    /// Two rows of four tiles, all at ground level
+    fn flat_map() -> Map {
+        test_map(&FLAT_TILES)
+    }
+
+    #[test]
+    fn route_steps_to_target() {
+        let map = flat_map();
+        let unit = test_unit((0.0, 0.0, 0.0), 4, 0.5, 0.5);
+
+        let route = unit.get_route(&[], Pos::new(3.0, 0.0, 0.0), &map);
+
+        assert_eq!(
+            route,
+            Some(vec![
+                Pos::new(1.0, 0.0, 0.0),
+                Pos::new(2.0, 0.0, 0.0),
+                Pos::new(3.0, 0.0, 0.0),
+            ])
+        );
+        assert!(unit.is_valid_route(&route.unwrap(), &map));
+    }
+
+    #[test]
+    fn route_visits_waypoints_in_order() {
+        let map = flat_map();
+        let unit = test_unit((0.0, 0.0, 0.0), 4, 0.5, 0.5);
+
+        let route = unit.get_route(&[Pos::new(3.0, 0.0, 0.0)], Pos::new(3.0, 0.0, 1.0), &map);
+
+        assert_eq!(
+            route,
+            Some(vec![
+                Pos::new(1.0, 0.0, 0.0),
+                Pos::new(2.0, 0.0, 0.0),
+                Pos::new(3.0, 0.0, 0.0),
+                Pos::new(3.0, 0.0, 1.0),
+            ])
         );
 
-        Unit::spawn_villager(
-            &mut commands,
-            &graphics,
-            screen_coords2,
-            *tile_entity2,
-            starting_pos2,
+        // the other way round, the waypoint is a step further than the unit can move
+        let route = unit.get_route(&[Pos::new(3.0, 0.0, 1.0)], Pos::new(3.0, 0.0, 0.0), &map);
+
+        assert_eq!(route, None);
+    }
+
+    #[test]
+    fn invalid_routes_are_rejected() {
+        let map = flat_map();
+        let unit = test_unit((0.0, 0.0, 0.0), 2, 0.5, 0.5);
+
+        // empty
+        assert!(!unit.is_valid_route(&[], &map));
+        // skips a tile
+        assert!(!unit.is_valid_route(&[Pos::new(2.0, 0.0, 0.0)], &map));
+        // diagonal
+        assert!(!unit.is_valid_route(&[Pos::new(1.0, 0.0, 1.0)], &map));
+        // longer than the unit can move
+        assert!(!unit.is_valid_route(
+            &[
+                Pos::new(1.0, 0.0, 0.0),
+                Pos::new(2.0, 0.0, 0.0),
+                Pos::new(3.0, 0.0, 0.0),
+            ],
+            &map
+        ));
+        assert_eq!(unit.get_route(&[], Pos::new(3.0, 0.0, 0.0), &map), None);
+    }
+
+    #[test]
+    fn routes_respect_climb_limit() {
+        let map = test_map(&[(0.0, 0.0, 0.0), (1.0, 1.0, 0.0)]);
+        let target = Pos::new(1.0, 1.0, 0.0);
+
+        let unit = test_unit((0.0, 0.0, 0.0), 2, 0.5, 0.5);
+        assert_eq!(unit.get_route(&[], target, &map), None);
+        assert!(!unit.is_valid_route(&[target], &map));
+
+        let unit = test_unit((0.0, 0.0, 0.0), 2, 1.0, 0.5);
+        assert_eq!(unit.get_route(&[], target, &map), Some(vec![target]));
+    }
+
+    #[test]
+    fn routes_respect_drop_limit() {
+        let map = test_map(&[(0.0, 1.0, 0.0), (1.0, 0.0, 0.0)]);
+        let target = Pos::new(1.0, 0.0, 0.0);
+
+        let unit = test_unit((0.0, 1.0, 0.0), 2, 0.5, 0.5);
+        assert_eq!(unit.get_route(&[], target, &map), None);
+        assert!(!unit.is_valid_route(&[target], &map));
+
+        let unit = test_unit((0.0, 1.0, 0.0), 2, 0.5, 1.0);
+        assert_eq!(unit.get_route(&[], target, &map), Some(vec![target]));
+    }
+
+    #[test]
+    fn routes_avoid_covered_tiles() {
+        // (1, 0, 0) is buried under (1, 1, 0)
+        let map = test_map(&[
+            (0.0, 0.0, 0.0),
+            (1.0, 0.0, 0.0),
+            (1.0, 1.0, 0.0),
+            (2.0, 0.0, 0.0),
+        ]);
+        let unit = test_unit((0.0, 0.0, 0.0), 3, 1.0, 1.0);
+
+        assert!(!unit.is_valid_route(&[Pos::new(1.0, 0.0, 0.0)], &map));
+        assert_eq!(unit.get_route(&[], Pos::new(1.0, 0.0, 0.0), &map), None);
+        assert_eq!(
+            unit.get_route(&[], Pos::new(2.0, 0.0, 0.0), &map),
+            Some(vec![Pos::new(1.0, 1.0, 0.0), Pos::new(2.0, 0.0, 0.0)])
+        );
+    }
+
+    #[test]
+    fn moves_pay_for_terrain() {
+        let map = terrain_map(&FLAT_TILES, &[((1.0, 0.0, 0.0), Terrain::Forest)]);
+        let unit = test_unit((0.0, 0.0, 0.0), 2, 0.5, 0.5);
+
+        let moves = unit.get_valid_moves(&map);
+
+        assert_eq!(moves[0], Pos::new(0.0, 0.0, 1.0));
+        assert_eq!(
+            moves.into_iter().collect::<HashSet<_>>(),
+            HashSet::from([
+                Pos::new(0.0, 0.0, 1.0),
+                Pos::new(1.0, 0.0, 0.0),
+                Pos::new(1.0, 0.0, 1.0),
+            ])
+        );
+    }
+
+    #[test]
+    fn routes_go_around_costly_terrain() {
+        let map = terrain_map(
+            &FLAT_TILES,
+            &[
+                ((1.0, 0.0, 0.0), Terrain::Marsh),
+                ((2.0, 0.0, 0.0), Terrain::Marsh),
+            ],
+        );
+        let unit = test_unit((0.0, 0.0, 0.0), 5, 0.5, 0.5);
+
+        assert_eq!(
+            unit.get_route(&[], Pos::new(3.0, 0.0, 0.0), &map),
+            Some(vec![
+                Pos::new(0.0, 0.0, 1.0),
+                Pos::new(1.0, 0.0, 1.0),
+                Pos::new(2.0, 0.0, 1.0),
+                Pos::new(3.0, 0.0, 1.0),
+                Pos::new(3.0, 0.0, 0.0),
+            ])
+        );
+    }
+
+    #[test]
+    fn routes_must_afford_their_terrain() {
+        let map = terrain_map(&FLAT_TILES, &[((1.0, 0.0, 0.0), Terrain::Forest)]);
+        let route = [Pos::new(1.0, 0.0, 0.0), Pos::new(2.0, 0.0, 0.0)];
+
+        assert!(!test_unit((0.0, 0.0, 0.0), 2, 0.5, 0.5).is_valid_route(&route, &map));
+        assert!(test_unit((0.0, 0.0, 0.0), 3, 0.5, 0.5).is_valid_route(&route, &map));
+    }
+
+    #[test]
+    fn cancel_steps_back_one_mode_at_a_time() {
+        assert_eq!(
+            SelectMode::AttackTarget.previous(),
+            Some(SelectMode::ActionMenu)
         );
+        assert_eq!(SelectMode::ActionMenu.previous(), Some(SelectMode::Move));
+        assert_eq!(SelectMode::Move.previous(), None);
     }
 }
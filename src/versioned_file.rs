@@ -0,0 +1,118 @@
+use std::{fs, path::Path};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// A RON file with a `version` field, so that files written by older versions of the game
+/// are rejected rather than misread
+pub(crate) trait VersionedFile: Serialize + DeserializeOwned {
+    /// Bump whenever the file changes shape
+    const VERSION: u32;
+    /// What the file is called in errors
+    const NAME: &'static str;
+}
+
+/// Just enough of a `VersionedFile` to tell which version it is
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+pub(crate) fn write_versioned<T: VersionedFile>(file: &T, path: &Path) -> anyhow::Result<()> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+
+    let contents = ron::ser::to_string_pretty(file, ron::ser::PrettyConfig::default())?;
+    fs::write(path, contents)?;
+
+    Ok(())
+}
+
+pub(crate) fn read_versioned<T: VersionedFile>(path: &Path) -> anyhow::Result<T> {
+    let bytes = fs::read(path)?;
+
+    // check the version before anything else, since older files may not parse as a `T`
+    let header: Header = ron::de::from_bytes(&bytes)?;
+    if header.version != T::VERSION {
+        anyhow::bail!(
+            "{} is version {}, expected version {}",
+            T::NAME,
+            header.version,
+            T::VERSION
+        );
+    }
+
+    Ok(ron::de::from_bytes(&bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct TestFile {
+        version: u32,
+        name: String,
+        values: Vec<u32>,
+    }
+
+    impl VersionedFile for TestFile {
+        const VERSION: u32 = 3;
+        const NAME: &'static str = "test file";
+    }
+
+    /// A path in the temp directory that's unique to this test run
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("zamlock-{}-{}.ron", name, std::process::id()))
+    }
+
+    fn test_file(version: u32) -> TestFile {
+        TestFile {
+            version,
+            name: "test".to_string(),
+            values: vec![1, 2, 3],
+        }
+    }
+
+    #[test]
+    fn file_round_trips() {
+        let path = temp_path("versioned-round-trip");
+        let file = test_file(TestFile::VERSION);
+
+        write_versioned(&file, &path).expect("Couldn't write file");
+        let loaded = read_versioned::<TestFile>(&path);
+        fs::remove_file(&path).ok();
+
+        assert_eq!(loaded.expect("Couldn't read file"), file);
+    }
+
+    #[test]
+    fn file_from_another_version_is_rejected() {
+        let path = temp_path("versioned-wrong-version");
+
+        write_versioned(&test_file(TestFile::VERSION + 1), &path).expect("Couldn't write file");
+        let loaded = read_versioned::<TestFile>(&path);
+        fs::remove_file(&path).ok();
+
+        let error = loaded.expect_err("Read a file with the wrong version");
+        assert_eq!(
+            error.to_string(),
+            "test file is version 4, expected version 3"
+        );
+    }
+
+    #[test]
+    fn old_file_is_rejected_by_version() {
+        let path = temp_path("versioned-old-shape");
+
+        // an older file that doesn't parse as the current `TestFile`
+        fs::write(&path, "(version: 1, names: [])").expect("Couldn't write file");
+        let loaded = read_versioned::<TestFile>(&path);
+        fs::remove_file(&path).ok();
+
+        let error = loaded.expect_err("Read a file with the wrong version");
+        assert!(error.to_string().contains("version 1"));
+    }
+}